@@ -0,0 +1,87 @@
+//! Shadow blur timings at common radii and resolutions
+//!
+//! Run with `cargo bench`; `cargo bench -- 3840x2160` narrows it to one size.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fweh::{add_drop_shadow, gaussian_blur, ShadowOptions};
+use image::{imageops, Rgba, RgbaImage};
+
+const RESOLUTIONS: [(u32, u32); 3] = [(1280, 720), (1920, 1080), (3840, 2160)];
+const RADII: [f32; 4] = [5.0, 25.0, 50.0, 100.0];
+
+/// An opaque rectangle inset on a transparent canvas, like a screenshot's mask
+fn mask(width: u32, height: u32) -> Vec<f32> {
+    let (inset_x, inset_y) = (width / 8, height / 8);
+    (0..height)
+        .flat_map(|y| {
+            (0..width).map(move |x| {
+                let inside = (inset_x..width - inset_x).contains(&x)
+                    && (inset_y..height - inset_y).contains(&y);
+                if inside {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+        })
+        .collect()
+}
+
+fn alpha_blur(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gaussian_blur");
+    group.sample_size(20);
+
+    for (width, height) in RESOLUTIONS {
+        let source = mask(width, height);
+        group.throughput(Throughput::Elements(source.len() as u64));
+
+        for radius in RADII {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}x{}", width, height), radius),
+                &radius,
+                |b, &radius| {
+                    let mut values = source.clone();
+                    b.iter(|| gaussian_blur(&mut values, width as usize, height as usize, radius));
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn imageops_baseline(c: &mut Criterion) {
+    // The RGBA blur shadows used before; too slow to run at every size
+    let mut group = c.benchmark_group("imageops_blur");
+    group.sample_size(10);
+
+    let (width, height) = RESOLUTIONS[0];
+    let image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    for radius in [5.0, 25.0] {
+        group.bench_with_input(
+            BenchmarkId::new(format!("{}x{}", width, height), radius),
+            &radius,
+            |b, &radius| b.iter(|| imageops::blur(&image, radius)),
+        );
+    }
+
+    group.finish();
+}
+
+fn drop_shadow(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_drop_shadow");
+    group.sample_size(10);
+
+    for (width, height) in RESOLUTIONS {
+        let image = RgbaImage::from_pixel(width, height, Rgba([40, 40, 60, 255]));
+        let options = ShadowOptions::default();
+        group.bench_function(format!("{}x{}", width, height), |b| {
+            b.iter(|| add_drop_shadow(&image, &options).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, alpha_blur, imageops_baseline, drop_shadow);
+criterion_main!(benches);
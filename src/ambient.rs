@@ -0,0 +1,193 @@
+//! Ambient backgrounds derived from the input image
+//!
+//! The input is scaled to cover the canvas, heavily blurred and then darkened
+//! and (de)saturated, so the sharp foreground floats on a glow of its own
+//! colors. Options are given as a comma-separated list, e.g.
+//! `radius=60,brightness=0.7,saturation=1.2`; a bare number is the radius.
+
+use anyhow::{anyhow, Result};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use log::debug;
+use rayon::prelude::*;
+
+use crate::background::resize_to_cover;
+use crate::gradient::Position;
+use crate::utils::{parse_option_number, parse_options};
+
+/// Largest blur sigma applied at full resolution
+///
+/// Larger blurs are computed on a downscaled copy of the image and scaled back
+/// up, which looks the same once everything is smoothed out but is far cheaper.
+const MAX_DIRECT_SIGMA: f32 = 8.0;
+
+/// Options for an ambient background
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOptions {
+    /// Blur radius (Gaussian sigma) in pixels of the output canvas
+    pub radius: f32,
+
+    /// Brightness multiplier; values below 1 darken the background
+    pub brightness: f32,
+
+    /// Saturation multiplier; 0 is greyscale, values above 1 boost color
+    pub saturation: f32,
+}
+
+impl Default for AmbientOptions {
+    fn default() -> Self {
+        Self {
+            radius: 60.0,
+            brightness: 0.7,
+            saturation: 1.2,
+        }
+    }
+}
+
+impl AmbientOptions {
+    /// Parse ambient options; an empty specification gives the defaults
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut options = Self::default();
+
+        for (key, value) in parse_options(spec) {
+            let key = if key.is_empty() {
+                "radius".to_string()
+            } else {
+                key.to_ascii_lowercase()
+            };
+
+            let field = match key.as_str() {
+                "radius" => &mut options.radius,
+                "brightness" => &mut options.brightness,
+                "saturation" => &mut options.saturation,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown ambient option '{}' (expected radius, brightness or saturation)",
+                        other
+                    ))
+                }
+            };
+
+            *field = parse_option_number(&format!("ambient {}", key), value)?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Create an ambient background from `source`
+pub fn create_ambient_background(
+    width: u32,
+    height: u32,
+    source: &RgbaImage,
+    spec: &str,
+) -> Result<RgbaImage> {
+    let options = AmbientOptions::parse(spec)?;
+    debug!("Creating ambient background: {:?}", options);
+
+    if width == 0 || height == 0 || source.width() == 0 || source.height() == 0 {
+        return Ok(RgbaImage::new(width, height));
+    }
+
+    let mut ambient = blur(
+        &resize_to_cover(source, width, height, Position::default()),
+        options.radius,
+    );
+    adjust_colors(&mut ambient, options.brightness, options.saturation);
+
+    Ok(ambient)
+}
+
+/// Gaussian-blur `image` with standard deviation `sigma`
+///
+/// Large blurs run on a reduced copy that is scaled back up afterwards.
+pub(crate) fn blur(image: &RgbaImage, sigma: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if sigma <= 0.0 || width == 0 || height == 0 {
+        return image.clone();
+    }
+
+    let factor = (sigma / MAX_DIRECT_SIGMA).max(1.0);
+    let work_width = ((width as f32 / factor).round() as u32).max(1);
+    let work_height = ((height as f32 / factor).round() as u32).max(1);
+
+    if (work_width, work_height) == (width, height) {
+        return imageops::blur(image, sigma);
+    }
+
+    let reduced = imageops::resize(image, work_width, work_height, FilterType::Triangle);
+    let blurred = imageops::blur(&reduced, sigma / factor);
+    imageops::resize(&blurred, width, height, FilterType::Triangle)
+}
+
+/// Scale brightness and saturation of every pixel in place
+pub(crate) fn adjust_colors(image: &mut RgbaImage, brightness: f32, saturation: f32) {
+    image.par_chunks_mut(4).for_each(|pixel| {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0);
+        let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+        for (channel, value) in pixel.iter_mut().zip([r, g, b]) {
+            let adjusted = (luma + (value - luma) * saturation) * brightness;
+            *channel = (adjusted.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(
+            AmbientOptions::parse("").unwrap(),
+            AmbientOptions::default()
+        );
+
+        let options = AmbientOptions::parse("radius=40,brightness=0.5,saturation=0").unwrap();
+        assert_eq!(options.radius, 40.0);
+        assert_eq!(options.brightness, 0.5);
+        assert_eq!(options.saturation, 0.0);
+
+        // A bare number is the radius, and keys are case-insensitive
+        let options = AmbientOptions::parse("25, Brightness=1").unwrap();
+        assert_eq!(options.radius, 25.0);
+        assert_eq!(options.brightness, 1.0);
+        assert_eq!(options.saturation, AmbientOptions::default().saturation);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let message = |spec| format!("{}", AmbientOptions::parse(spec).unwrap_err());
+
+        assert_eq!(
+            message("brightness=-1"),
+            "Invalid ambient brightness '-1': expected a non-negative number"
+        );
+        assert_eq!(
+            message("radius=inf"),
+            "Invalid ambient radius 'inf': expected a non-negative number"
+        );
+        assert_eq!(
+            message("wide"),
+            "Invalid ambient radius 'wide': expected a non-negative number"
+        );
+        assert_eq!(
+            message("glow=1"),
+            "Unknown ambient option 'glow' (expected radius, brightness or saturation)"
+        );
+    }
+
+    #[test]
+    fn adjusts_brightness_and_saturation() {
+        let mut image = RgbaImage::from_pixel(1, 1, image::Rgba([200, 100, 50, 255]));
+        adjust_colors(&mut image, 1.0, 0.0);
+        let [r, g, b, a] = image.get_pixel(0, 0).0;
+        assert!(r == g && g == b, "saturation 0 is greyscale");
+        assert_eq!(a, 255);
+
+        let mut image = RgbaImage::from_pixel(1, 1, image::Rgba([200, 100, 50, 255]));
+        adjust_colors(&mut image, 0.5, 1.0);
+        assert_eq!(image.get_pixel(0, 0).0, [100, 50, 25, 255]);
+    }
+}
@@ -1,126 +1,243 @@
 //! Command line argument parsing
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use std::path::PathBuf;
 
-use crate::background::BackgroundType;
-use crate::image_processing::{AspectRatio, ProcessingOptions};
-use crate::shadow::ShadowOptions;
-use crate::utils::Point;
+use crate::config::{Preset, ShadowPreset};
+use fweh::{
+    parse_shadows, AspectRatio, BackgroundLayer, BackgroundType, BlendMode, Elevation,
+    NoiseOptions, Point, ProcessingOptions, ShadowOptions,
+};
 
 /// Command line arguments for the image framer tool
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Input image file
-    pub input: PathBuf,
+    /// Input image files, directories or glob patterns
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
 
-    /// Output filename
-    #[arg(short, long, default_value = "output.png")]
-    pub output: PathBuf,
+    /// Output filename for a single input (default: output.png)
+    #[arg(short, long, conflicts_with = "out_dir")]
+    pub output: Option<PathBuf>,
 
-    /// Scale percentage
-    #[arg(short, long, default_value_t = 110.0)]
-    pub scale: f32,
+    /// Directory to write framed images to (enables batch naming)
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Output filename template for batch mode ({stem}, {ext}, {name}, {index})
+    #[arg(long, default_value = fweh::DEFAULT_NAME_TEMPLATE)]
+    pub name: String,
+
+    /// Number of images to process in parallel (defaults to the number of CPUs)
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Named preset from the config file (flags override its values)
+    #[arg(short, long)]
+    pub preset: Option<String>,
+
+    /// Print the dominant colors of each input instead of framing it
+    #[arg(long)]
+    pub print_palette: bool,
+
+    /// Config file to read presets from (defaults to fweh.toml and the user config)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Scale percentage [default: 110]
+    #[arg(short, long)]
+    pub scale: Option<f32>,
 
-    /// Background type and value (e.g. colr:black, grad:blue-red, imag:/path/to/image.png)
+    /// Background type and value (e.g. colr:black, grad:blue-red, grad:radial(circle, red, blue), mesh:points=5,seed=3, imag:/path/to/image.png, blur:radius=60, auto:gradient, patt:dots,spacing=24); repeat to stack layers, each optionally followed by ;opacity=0.5;blend=multiply
     #[arg(short, long)]
-    pub background: Option<String>,
+    pub background: Vec<String>,
+
+    /// Grain over the background (e.g. gaussian, perlin,amount=0.08,scale=6, uniform,chroma,seed=7)
+    #[arg(long)]
+    pub noise: Option<String>,
 
     /// Target aspect ratio (e.g. 16:9)
     #[arg(short, long)]
     pub ratio: Option<String>,
 
-    /// Border radius percentage (0-100)
-    #[arg(long, default_value_t = 0.0)]
-    pub roundness: f32,
+    /// Border radius percentage (0-100) [default: 0]
+    #[arg(long)]
+    pub roundness: Option<f32>,
 
-    /// Image offset in pixels (e.g. 0,0)
-    #[arg(long, default_value = "0,0")]
-    pub offset: String,
+    /// Image offset in pixels (e.g. 0,0) [default: 0,0]
+    #[arg(long)]
+    pub offset: Option<String>,
 
     /// Shadow offset in pixels (e.g. 25,25)
     #[arg(long)]
     pub shadow_offset: Option<String>,
 
-    /// Shadow color (e.g. black, #000000)
-    #[arg(long, default_value = "black")]
-    pub shadow_color: String,
+    /// Shadow color (e.g. black, #000000) [default: black]
+    #[arg(long)]
+    pub shadow_color: Option<String>,
 
-    /// Shadow blur radius
-    #[arg(long, default_value_t = 25.0)]
-    pub shadow_radius: f32,
+    /// Shadow blur radius [default: 25]
+    #[arg(long)]
+    pub shadow_radius: Option<f32>,
 
-    /// Shadow opacity (0.0-1.0)
-    #[arg(long, default_value_t = 1.0)]
-    pub shadow_opacity: f32,
-}
+    /// Shadow opacity (0.0-1.0) [default: 1]
+    #[arg(long)]
+    pub shadow_opacity: Option<f32>,
 
-fn has_shadow_options(args: &Args) -> bool {
-    if args.shadow_offset.is_some() {
-        return true;
-    } else {
-        return false;
-    }
-}
+    /// Pixels to grow (or, if negative, shrink) the shadow by before blurring [default: 0]
+    #[arg(long, allow_negative_numbers = true)]
+    pub shadow_spread: Option<f32>,
 
-impl From<Args> for ProcessingOptions {
-    fn from(args: Args) -> Self {
-        let offset = parse_point(&args.offset).unwrap_or(Point::new(0.0, 0.0));
+    /// How the shadow blends with the background (normal, multiply, overlay, color-burn) [default: normal]
+    #[arg(long)]
+    pub shadow_blend: Option<String>,
 
-        let shadow = if has_shadow_options(&args) {
-            let shadow_offset = parse_point(args.shadow_offset.as_ref().unwrap())
-                .unwrap_or(Point::new(25.0, -25.0));
+    /// Add a shadow like CSS box-shadow: "<x> <y> [blur] [spread] [color]" (repeatable, first on top)
+    #[arg(long = "shadow", value_name = "SHADOW", allow_hyphen_values = true)]
+    pub shadows: Vec<String>,
 
-            Some(ShadowOptions {
-                offset: shadow_offset,
-                color: args.shadow_color,
-                radius: args.shadow_radius,
-                opacity: args.shadow_opacity,
-            })
+    /// Shadow stack for an elevation level (0-5) or name (subtle, floating, modal)
+    #[arg(long)]
+    pub elevation: Option<String>,
+
+    /// Disable the shadow, even if the preset enables one
+    #[arg(long, conflicts_with_all = ["shadow_offset", "shadows", "elevation"])]
+    pub no_shadow: bool,
+}
+
+impl Args {
+    /// The processing options given on the command line, as a preset layer
+    pub fn overrides(&self) -> Preset {
+        // Only an explicit shadow offset enables the shadow from the command line;
+        // the other shadow flags just tweak a shadow that is enabled by a preset
+        let enabled = if self.no_shadow {
+            Some(false)
         } else {
-            None
+            self.shadow_offset.as_ref().map(|_| true)
         };
 
-        let ratio = args.ratio.as_ref().and_then(|r| {
-            let parts: Vec<&str> = r.split(':').collect();
-            if parts.len() == 2 {
-                let width = parts[0].parse::<u32>().ok()?;
-                let height = parts[1].parse::<u32>().ok()?;
-                Some(AspectRatio { width, height })
+        let shadow = ShadowPreset {
+            enabled,
+            offset: self.shadow_offset.clone(),
+            color: self.shadow_color.clone(),
+            radius: self.shadow_radius,
+            opacity: self.shadow_opacity,
+            spread: self.shadow_spread,
+            blend: self.shadow_blend.clone(),
+        };
+
+        Preset {
+            scale: self.scale,
+            roundness: self.roundness,
+            offset: self.offset.clone(),
+            background: (!self.background.is_empty()).then(|| self.background.clone()),
+            noise: self.noise.clone(),
+            ratio: self.ratio.clone(),
+            shadow: Some(shadow),
+            shadows: if self.no_shadow {
+                Some(Vec::new())
+            } else {
+                (!self.shadows.is_empty()).then(|| self.shadows.clone())
+            },
+            elevation: if self.no_shadow {
+                Some("0".to_string())
             } else {
-                None
+                self.elevation.clone()
+            },
+        }
+    }
+}
+
+/// Resolve a fully layered preset into processing options
+///
+/// Fields that are still unset fall back to the [`ProcessingOptions`] and
+/// [`ShadowOptions`] defaults.
+pub fn resolve_options(preset: Preset) -> Result<ProcessingOptions> {
+    let defaults = ProcessingOptions::default();
+
+    let offset = match &preset.offset {
+        Some(offset) => parse_point(offset).context("Invalid --offset")?,
+        None => defaults.offset,
+    };
+
+    let mut shadows = Vec::new();
+    for spec in preset.shadows.iter().flatten() {
+        shadows.extend(parse_shadows(spec).context("Invalid --shadow")?);
+    }
+
+    let shadow = match preset.shadow {
+        Some(shadow) if shadow.enabled.unwrap_or(false) => {
+            let shadow_defaults = ShadowOptions::default();
+            let offset = match &shadow.offset {
+                Some(offset) => parse_point(offset).context("Invalid --shadow-offset")?,
+                None => shadow_defaults.offset,
+            };
+            let blend = match &shadow.blend {
+                Some(blend) => BlendMode::parse(blend).context("Invalid --shadow-blend")?,
+                None => shadow_defaults.blend,
+            };
+            let spread = shadow.spread.unwrap_or(shadow_defaults.spread);
+            if !spread.is_finite() {
+                return Err(anyhow!("Invalid --shadow-spread: {} is not finite", spread));
             }
-        });
-
-        let background = args
-            .background
-            .as_ref()
-            .and_then(|bg| {
-                let parts: Vec<&str> = bg.split(':').collect();
-                if parts.len() == 2 {
-                    match parts[0] {
-                        "colr" => Some(BackgroundType::Color(parts[1].to_string())),
-                        "grad" => Some(BackgroundType::Gradient(parts[1].to_string())),
-                        "imag" => Some(BackgroundType::Image(parts[1].to_string())),
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
+
+            Some(ShadowOptions {
+                offset,
+                color: shadow.color.unwrap_or(shadow_defaults.color),
+                radius: shadow.radius.unwrap_or(shadow_defaults.radius),
+                opacity: shadow.opacity.unwrap_or(shadow_defaults.opacity),
+                spread,
+                blend,
             })
-            .unwrap_or(BackgroundType::Color("black".to_string()));
-
-        ProcessingOptions {
-            scale: args.scale,
-            roundness: args.roundness,
-            offset,
-            shadow,
-            background,
-            ratio,
         }
-    }
+        _ => None,
+    };
+    // The shadow from the single-shadow flags goes under any stacked ones
+    shadows.extend(shadow);
+
+    let elevation = preset
+        .elevation
+        .as_deref()
+        .map(Elevation::parse)
+        .transpose()
+        .context("Invalid --elevation")?;
+
+    let ratio = preset
+        .ratio
+        .as_deref()
+        .map(parse_ratio)
+        .transpose()
+        .context("Invalid --ratio")?;
+
+    let backgrounds = match &preset.background {
+        Some(layers) => layers
+            .iter()
+            .map(|layer| {
+                parse_layer(layer).with_context(|| format!("Invalid --background '{}'", layer))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => defaults.backgrounds,
+    };
+
+    let noise = preset
+        .noise
+        .as_deref()
+        .map(NoiseOptions::parse)
+        .transpose()
+        .context("Invalid --noise")?;
+
+    Ok(ProcessingOptions {
+        scale: preset.scale.unwrap_or(defaults.scale),
+        roundness: preset.roundness.unwrap_or(defaults.roundness),
+        offset,
+        shadows,
+        elevation,
+        backgrounds,
+        noise,
+        ratio,
+    })
 }
 
 /// Parse command line arguments
@@ -141,3 +258,139 @@ fn parse_point(input: &str) -> Result<Point> {
 
     Ok(Point::new(x, y))
 }
+
+/// Parse an aspect ratio from a string (e.g. "16:9")
+fn parse_ratio(input: &str) -> Result<AspectRatio> {
+    let (width, height) = input
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid aspect ratio format: {}", input))?;
+
+    let width = width.trim().parse::<u32>()?;
+    let height = height.trim().parse::<u32>()?;
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Aspect ratio sides must be positive: {}", input));
+    }
+
+    Ok(AspectRatio { width, height })
+}
+
+/// Parse a background layer (e.g. "patt:dots;opacity=0.1;blend=multiply")
+fn parse_layer(input: &str) -> Result<BackgroundLayer> {
+    let mut parts = input.split(';');
+    let mut layer =
+        BackgroundLayer::from(parse_background(parts.next().unwrap_or_default().trim())?);
+
+    for option in parts.map(str::trim).filter(|option| !option.is_empty()) {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid layer option: {}", option))?;
+
+        match key.trim() {
+            "opacity" => {
+                layer.opacity = value
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|opacity| (0.0..=1.0).contains(opacity))
+                    .ok_or_else(|| anyhow!("Layer opacity must be between 0 and 1: {}", value))?
+            }
+            "blend" => layer.blend = BlendMode::parse(value)?,
+            other => {
+                return Err(anyhow!(
+                    "Unknown layer option '{}' (expected opacity or blend)",
+                    other
+                ))
+            }
+        }
+    }
+
+    Ok(layer)
+}
+
+/// Parse a background specification (e.g. "colr:black")
+fn parse_background(input: &str) -> Result<BackgroundType> {
+    match input {
+        "auto" => return Ok(BackgroundType::Auto(String::new())),
+        "transparent" | "none" => return Ok(BackgroundType::Color("transparent".to_string())),
+        _ => {}
+    }
+
+    let (kind, value) = input
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid background format: {}", input))?;
+
+    match kind {
+        "colr" => Ok(BackgroundType::Color(value.to_string())),
+        "grad" => {
+            let function = value.split('(').next().unwrap_or_default().trim();
+            match function.to_ascii_lowercase().as_str() {
+                "radial" | "radial-gradient" => {
+                    Ok(BackgroundType::RadialGradient(value.to_string()))
+                }
+                "conic" | "conic-gradient" => Ok(BackgroundType::ConicGradient(value.to_string())),
+                _ => Ok(unwrapped_gradient(value)),
+            }
+        }
+        "mesh" => Ok(BackgroundType::Mesh(value.to_string())),
+        "imag" => Ok(BackgroundType::Image(value.to_string())),
+        "blur" => Ok(BackgroundType::Ambient(value.to_string())),
+        "auto" => Ok(BackgroundType::Auto(value.to_string())),
+        "patt" => {
+            let (pattern, options) = value.split_once(',').unwrap_or((value, ""));
+            let options = options.to_string();
+            match pattern.trim().to_ascii_lowercase().as_str() {
+                "dots" => Ok(BackgroundType::Dots(options)),
+                "grid" => Ok(BackgroundType::Grid(options)),
+                "stripes" => Ok(BackgroundType::Stripes(options)),
+                "checkerboard" | "checker" => Ok(BackgroundType::Checkerboard(options)),
+                other => Err(anyhow!(
+                    "Unknown pattern: {} (expected dots, grid, stripes or checkerboard)",
+                    other
+                )),
+            }
+        }
+        other => Err(anyhow!("Unknown background type: {}", other)),
+    }
+}
+
+/// Pick the gradient type of a spec without a function wrapper from its first argument
+///
+/// `from` opens a conic gradient and a shape, extent or `at` a radial one, so
+/// `circle, white, navy` works like `radial(circle, white, navy)`. Anything
+/// else is a linear gradient.
+fn unwrapped_gradient(spec: &str) -> BackgroundType {
+    let setup = spec
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let has = |keywords: &[&str]| setup.split_whitespace().any(|t| keywords.contains(&t));
+
+    if has(&["from"]) {
+        BackgroundType::ConicGradient(spec.to_string())
+    } else if has(&[
+        "circle",
+        "ellipse",
+        "closest-side",
+        "closest-corner",
+        "farthest-side",
+        "farthest-corner",
+        "at",
+    ]) {
+        BackgroundType::RadialGradient(spec.to_string())
+    } else {
+        BackgroundType::Gradient(spec.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow_accepts_negative_offsets() {
+        let args =
+            Args::try_parse_from(["fweh", "in.png", "--shadow", "-4px 2px 3px black"]).unwrap();
+        assert_eq!(args.shadows, ["-4px 2px 3px black"]);
+    }
+}
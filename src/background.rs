@@ -1,11 +1,24 @@
 //! Background generation
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use image::imageops::{self, FilterType};
 use image::RgbaImage;
 use log::debug;
-use rgb::{Rgba, RGBA8};
+use std::path::Path;
 
+use crate::ambient::{adjust_colors, blur, create_ambient_background};
+use crate::blend::BlendMode;
+pub use crate::color::parse_color;
+use crate::compositor::{self, blend_layer};
+use crate::error::FwehError;
+use crate::gradient::{
+    create_conic_gradient, create_gradient, create_radial_gradient, parse_position, Position,
+};
 use crate::image_processing::to_image_rgba;
+use crate::mesh::create_mesh_gradient;
+use crate::palette::{auto_background_spec, AutoMode};
+use crate::pattern::{create_pattern, PatternKind};
+use crate::utils::{parse_option_number, parse_options};
 
 /// Types of backgrounds supported by the image framer
 #[derive(Debug, Clone)]
@@ -13,18 +26,95 @@ pub enum BackgroundType {
     /// Solid color background (e.g. "black", "#FF0000")
     Color(String),
 
-    /// Gradient background (e.g. "blue-red", "linear:red-green-blue")
+    /// Gradient background (e.g. "blue-red", "linear(135deg, red, blue)")
     Gradient(String),
 
-    /// Image background (path to an image file)
+    /// Radial gradient background (e.g. "radial(circle closest-side at 30% 40%, white, navy)")
+    RadialGradient(String),
+
+    /// Conic gradient background (e.g. "conic(from 45deg at center, red, yellow, red)")
+    ConicGradient(String),
+
+    /// Image background (path to an image file, optionally followed by fit options
+    /// such as "wall.jpg,fit=contain,fill=white")
     Image(String),
+
+    /// Blurred copy of the input image (e.g. "radius=60,brightness=0.7,saturation=1.2")
+    Ambient(String),
+
+    /// Color picked from the input's palette ("dominant", "complementary" or "gradient")
+    Auto(String),
+
+    /// Mesh gradient of colored control points (e.g. "#ff0080 20% 30%, #00d4ff 80% 70%, seed=3")
+    Mesh(String),
+
+    /// Dot pattern (e.g. "spacing=24,size=2,color=#ccc,background=white")
+    Dots(String),
+
+    /// Grid line pattern (e.g. "spacing=32,size=1")
+    Grid(String),
+
+    /// Striped pattern (e.g. "spacing=20,size=6,angle=45deg")
+    Stripes(String),
+
+    /// Checkerboard pattern (e.g. "spacing=16,color=#eee,background=white")
+    Checkerboard(String),
+}
+
+/// One layer of a background stack
+#[derive(Debug, Clone)]
+pub struct BackgroundLayer {
+    pub background: BackgroundType,
+
+    /// Opacity of the layer (0.0-1.0)
+    pub opacity: f32,
+
+    /// How the layer combines with the layers beneath it
+    pub blend: BlendMode,
+}
+
+impl From<BackgroundType> for BackgroundLayer {
+    fn from(background: BackgroundType) -> Self {
+        Self {
+            background,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+/// Create every layer of a background stack and composite them bottom to top
+///
+/// The stack starts out transparent, so the first layer is usually opaque.
+pub fn create_layered_background(
+    new_width: u32,
+    new_height: u32,
+    layers: &[BackgroundLayer],
+    source: Option<&RgbaImage>,
+) -> Result<RgbaImage> {
+    let mut canvas = RgbaImage::new(new_width, new_height);
+
+    for layer in layers {
+        let image = create_background(new_width, new_height, &layer.background, source)?;
+        debug!(
+            "Compositing background layer with opacity {} and {:?} blending",
+            layer.opacity, layer.blend
+        );
+        blend_layer(&mut canvas, &image, layer.blend, layer.opacity);
+    }
+
+    Ok(canvas)
 }
 
 /// Create a background image with the given parameters
+///
+/// `source` is the image being framed; backgrounds derived from it, such as
+/// [`BackgroundType::Ambient`] and [`BackgroundType::Auto`], fail without one.
 pub fn create_background(
     new_width: u32,
     new_height: u32,
     background: &BackgroundType,
+    source: Option<&RgbaImage>,
 ) -> Result<RgbaImage> {
     debug!(
         "Creating background of type {:?} with dimensions {}x{}",
@@ -36,10 +126,53 @@ pub fn create_background(
         BackgroundType::Gradient(gradient) => {
             create_gradient_background(new_width, new_height, gradient)
         }
-        BackgroundType::Image(path) => create_image_background(new_width, new_height, path),
+        BackgroundType::RadialGradient(gradient) => {
+            debug!("Creating radial gradient background: {}", gradient);
+            create_radial_gradient(new_width, new_height, gradient)
+        }
+        BackgroundType::ConicGradient(gradient) => {
+            debug!("Creating conic gradient background: {}", gradient);
+            create_conic_gradient(new_width, new_height, gradient)
+        }
+        BackgroundType::Mesh(spec) => create_mesh_gradient(new_width, new_height, spec),
+        BackgroundType::Image(spec) => create_image_background(new_width, new_height, spec),
+        BackgroundType::Ambient(spec) => {
+            create_ambient_background(new_width, new_height, require_source(source)?, spec)
+        }
+        BackgroundType::Auto(mode) => {
+            let mode = AutoMode::parse(mode)?;
+            let spec = auto_background_spec(require_source(source)?, mode)?;
+            debug!("Automatic {:?} background: {}", mode, spec);
+
+            match mode {
+                AutoMode::Gradient => create_gradient_background(new_width, new_height, &spec),
+                AutoMode::Dominant | AutoMode::Complementary => {
+                    create_color_background(new_width, new_height, &spec)
+                }
+            }
+        }
+        BackgroundType::Dots(spec) => {
+            create_pattern(new_width, new_height, PatternKind::Dots, spec)
+        }
+        BackgroundType::Grid(spec) => {
+            create_pattern(new_width, new_height, PatternKind::Grid, spec)
+        }
+        BackgroundType::Stripes(spec) => {
+            create_pattern(new_width, new_height, PatternKind::Stripes, spec)
+        }
+        BackgroundType::Checkerboard(spec) => {
+            create_pattern(new_width, new_height, PatternKind::Checkerboard, spec)
+        }
     }
 }
 
+/// The source image, for backgrounds that are derived from it
+fn require_source(source: Option<&RgbaImage>) -> Result<&RgbaImage> {
+    source.ok_or_else(|| {
+        FwehError::BackgroundError("this background needs the source image".to_string()).into()
+    })
+}
+
 /// Create a solid color background
 fn create_color_background(width: u32, height: u32, color: &str) -> Result<RgbaImage> {
     debug!("Creating color background: {}", color);
@@ -60,173 +193,316 @@ fn create_color_background(width: u32, height: u32, color: &str) -> Result<RgbaI
 fn create_gradient_background(width: u32, height: u32, gradient: &str) -> Result<RgbaImage> {
     debug!("Creating gradient background: {}", gradient);
 
-    // Parse gradient specification
-    let colors = parse_gradient(gradient)?;
-    if colors.len() < 2 {
-        return Err(anyhow!("Gradient needs at least two colors").into());
-    }
+    create_gradient(width, height, gradient)
+}
+
+/// How an image background is fitted to the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFit {
+    /// Scale to cover the whole canvas, cropping the overflow around the anchor
+    #[default]
+    Cover,
+
+    /// Scale to fit inside the canvas, filling the remaining space
+    Contain,
+
+    /// Repeat the image across the canvas
+    Tile,
+
+    /// Scale to the exact canvas size, ignoring the aspect ratio
+    Stretch,
+
+    /// Place the image unscaled at the anchor
+    Center,
+}
 
-    // Create a new image
-    let mut img = RgbaImage::new(width, height);
+/// Options for an image background
+#[derive(Debug, Clone)]
+pub struct ImageBackgroundOptions {
+    /// Path to the image file
+    pub path: String,
+
+    pub fit: ImageFit,
+
+    /// Where the image sits on the canvas, like CSS `background-position`
+    pub anchor: Position,
 
-    // Simple linear gradient from top to bottom
-    for y in 0..height {
-        let progress = y as f32 / height as f32;
-        let index = (progress * (colors.len() - 1) as f32) as usize;
-        let next_index = (index + 1).min(colors.len() - 1);
-        let local_progress = progress * (colors.len() - 1) as f32 - index as f32;
+    /// Color behind the image where it does not cover the canvas
+    pub fill: String,
 
-        let color = interpolate_color(colors[index], colors[next_index], local_progress);
+    /// Scale factor of each tile in tile mode
+    pub scale: f32,
+
+    /// Gaussian blur radius applied to the fitted image
+    pub blur: f32,
+
+    /// How much to darken the fitted image (0.0-1.0)
+    pub dim: f32,
+}
 
-        for x in 0..width {
-            img.put_pixel(x, y, to_image_rgba(color));
+impl ImageBackgroundOptions {
+    /// Parse `path[,fit=...,anchor=...,fill=...,scale=...,blur=...,dim=...]`
+    ///
+    /// A value naming an existing file is always taken as a bare path, so
+    /// paths containing commas keep working.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut options = Self {
+            path: spec.trim().to_string(),
+            fit: ImageFit::default(),
+            anchor: Position::default(),
+            fill: "black".to_string(),
+            scale: 1.0,
+            blur: 0.0,
+            dim: 0.0,
+        };
+        if Path::new(spec.trim()).is_file() {
+            return Ok(options);
         }
-    }
 
-    Ok(img)
+        let entries = parse_options(spec);
+        let Some((("", path), rest)) = entries.split_first() else {
+            return Err(anyhow!("Image background is missing a path: {}", spec));
+        };
+        options.path = path.to_string();
+
+        for &(key, value) in rest {
+            let number = || parse_option_number(&format!("image {}", key), value);
+
+            match key.to_ascii_lowercase().as_str() {
+                "fit" => {
+                    options.fit = match value.to_ascii_lowercase().as_str() {
+                        "cover" => ImageFit::Cover,
+                        "contain" => ImageFit::Contain,
+                        "tile" | "repeat" => ImageFit::Tile,
+                        "stretch" => ImageFit::Stretch,
+                        "center" | "none" => ImageFit::Center,
+                        other => {
+                            return Err(anyhow!(
+                        "Unknown image fit '{}' (expected cover, contain, tile, stretch or center)",
+                        other
+                    ))
+                        }
+                    }
+                }
+                "anchor" => {
+                    let tokens = value.split_whitespace().collect::<Vec<_>>();
+                    options.anchor = parse_position(&tokens)
+                        .with_context(|| format!("Invalid image anchor '{}'", value))?;
+                }
+                "fill" => options.fill = value.to_string(),
+                "scale" => {
+                    options.scale = number()?;
+                    if options.scale == 0.0 {
+                        return Err(anyhow!("Image tile scale must be positive"));
+                    }
+                }
+                "blur" => options.blur = number()?,
+                "dim" => options.dim = number()?.min(1.0),
+                "" => return Err(anyhow!("Unexpected value '{}' in image background", value)),
+                other => {
+                    return Err(anyhow!(
+                    "Unknown image option '{}' (expected fit, anchor, fill, scale, blur or dim)",
+                    other
+                ))
+                }
+            }
+        }
+
+        Ok(options)
+    }
 }
 
 /// Create an image background from an existing image file
-fn create_image_background(width: u32, height: u32, path: &str) -> Result<RgbaImage> {
-    debug!("Creating image background from: {}", path);
+fn create_image_background(width: u32, height: u32, spec: &str) -> Result<RgbaImage> {
+    let options = ImageBackgroundOptions::parse(spec)?;
+    debug!("Creating image background: {:?}", options);
 
     // Load the background image
-    let bg_image = image::open(path)?;
-
-    // Resize the image to fit the new dimensions
-    let resized = bg_image.resize_to_fill(width, height, image::imageops::FilterType::Lanczos3);
-
-    // Convert to RGBA
-    let rgba = resized.to_rgba8();
-
-    Ok(rgba)
-}
-
-/// Parse a color string to an RGBA value
-pub fn parse_color(color: &str) -> Result<RGBA8> {
-    // Handle hex colors
-    if color.starts_with('#') {
-        let hex = color.trim_start_matches('#');
-
-        match hex.len() {
-            6 => {
-                // RGB format
-                let r = u8::from_str_radix(&hex[0..2], 16)?;
-                let g = u8::from_str_radix(&hex[2..4], 16)?;
-                let b = u8::from_str_radix(&hex[4..6], 16)?;
-                Ok(rgb::Rgba {
-                    r: (r),
-                    g: (g),
-                    b: (b),
-                    a: 255,
-                })
-            }
-            8 => {
-                // RGBA format
-                let r = u8::from_str_radix(&hex[0..2], 16)?;
-                let g = u8::from_str_radix(&hex[2..4], 16)?;
-                let b = u8::from_str_radix(&hex[4..6], 16)?;
-                let a = u8::from_str_radix(&hex[6..8], 16)?;
-                Ok(rgb::Rgba {
-                    r: (r),
-                    g: (g),
-                    b: (b),
-                    a: (a),
-                })
-            }
-            3 => {
-                // Short RGB format
-                let r = u8::from_str_radix(&hex[0..1], 16)? * 17;
-                let g = u8::from_str_radix(&hex[1..2], 16)? * 17;
-                let b = u8::from_str_radix(&hex[2..3], 16)? * 17;
-                Ok(rgb::Rgba {
-                    r: (r),
-                    g: (g),
-                    b: (b),
-                    a: 255,
-                })
-            }
-            _ => Err(anyhow!("Invalid hex color format: {}", color)),
-        }
-    } else {
-        // Handle named colors
-        match color.to_lowercase().as_str() {
-            "black" => Ok(Rgba {
-                r: 0,
-                g: 0,
-                b: 0,
-                a: 255,
-            }),
-            "white" => Ok(Rgba {
-                r: 255,
-                g: 255,
-                b: 255,
-                a: 255,
-            }),
-            "red" => Ok(Rgba {
-                r: 255,
-                g: 0,
-                b: 0,
-                a: 255,
-            }),
-            "green" => Ok(Rgba {
-                r: 0,
-                g: 255,
-                b: 0,
-                a: 255,
-            }),
-            "blue" => Ok(Rgba {
-                r: 0,
-                g: 0,
-                b: 255,
-                a: 255,
-            }),
-            "yellow" => Ok(Rgba {
-                r: 255,
-                g: 255,
-                b: 0,
-                a: 255,
-            }),
-            "cyan" => Ok(Rgba {
-                r: 0,
-                g: 255,
-                b: 255,
-                a: 255,
-            }),
-            "magenta" => Ok(Rgba {
-                r: 255,
-                g: 0,
-                b: 255,
-                a: 255,
-            }),
-            "transparent" => Ok(Rgba {
-                r: 0,
-                g: 0,
-                b: 0,
-                a: 0,
-            }),
-            other => Err(anyhow!("Unknown color name: {}", other)),
+    let image = image::open(&options.path)?.to_rgba8();
+
+    let mut canvas = match options.fit {
+        ImageFit::Cover => resize_to_cover(&image, width, height, options.anchor),
+        ImageFit::Stretch => imageops::resize(&image, width, height, FilterType::Lanczos3),
+        ImageFit::Contain => {
+            let scale =
+                (width as f32 / image.width() as f32).min(height as f32 / image.height() as f32);
+            let fitted = resize_by(&image, scale);
+            place(width, height, &fitted, options.anchor, &options.fill)?
         }
+        ImageFit::Center => place(width, height, &image, options.anchor, &options.fill)?,
+        ImageFit::Tile => tile(
+            width,
+            height,
+            &resize_by(&image, options.scale),
+            options.anchor,
+        )?,
+    };
+
+    if options.blur > 0.0 {
+        canvas = blur(&canvas, options.blur);
+    }
+    if options.dim > 0.0 {
+        adjust_colors(&mut canvas, 1.0 - options.dim, 1.0);
+    }
+
+    Ok(canvas)
+}
+
+/// Scale `image` to cover `width`x`height`, cropping the overflow around `anchor`
+pub(crate) fn resize_to_cover(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    anchor: Position,
+) -> RgbaImage {
+    let scale = (width as f32 / image.width() as f32).max(height as f32 / image.height() as f32);
+    let scaled_width = ((image.width() as f32 * scale).ceil() as u32).max(width);
+    let scaled_height = ((image.height() as f32 * scale).ceil() as u32).max(height);
+    let scaled = imageops::resize(image, scaled_width, scaled_height, FilterType::Lanczos3);
+
+    // Like CSS, the anchor is resolved against the overflow
+    let (overflow_x, overflow_y) = (scaled_width - width, scaled_height - height);
+    let x = anchor
+        .x
+        .resolve(overflow_x as f32)
+        .round()
+        .clamp(0.0, overflow_x as f32) as u32;
+    let y = anchor
+        .y
+        .resolve(overflow_y as f32)
+        .round()
+        .clamp(0.0, overflow_y as f32) as u32;
+
+    imageops::crop_imm(&scaled, x, y, width, height).to_image()
+}
+
+/// Resize `image` by a scale factor, keeping at least one pixel per side
+fn resize_by(image: &RgbaImage, scale: f32) -> RgbaImage {
+    if scale == 1.0 {
+        return image.clone();
     }
+
+    let width = ((image.width() as f32 * scale).round() as u32).max(1);
+    let height = ((image.height() as f32 * scale).round() as u32).max(1);
+    imageops::resize(image, width, height, FilterType::Lanczos3)
+}
+
+/// Draw `image` at `anchor` on a canvas filled with `fill`
+fn place(
+    width: u32,
+    height: u32,
+    image: &RgbaImage,
+    anchor: Position,
+    fill: &str,
+) -> Result<RgbaImage> {
+    let mut canvas = create_color_background(width, height, fill)?;
+    let x = anchor
+        .x
+        .resolve(width as f32 - image.width() as f32)
+        .round() as i64;
+    let y = anchor
+        .y
+        .resolve(height as f32 - image.height() as f32)
+        .round() as i64;
+    compositor::over(&mut canvas, image, x, y);
+
+    Ok(canvas)
 }
 
-/// Parse a gradient specification into a list of colors
-fn parse_gradient(gradient: &str) -> Result<Vec<RGBA8>> {
-    let parts = gradient.split('-').collect::<Vec<_>>();
-    let mut colors = Vec::with_capacity(parts.len());
-    for part in parts {
-        colors.push(parse_color(part)?);
+/// Repeat `image` over the canvas, with one tile placed at `anchor`
+fn tile(width: u32, height: u32, image: &RgbaImage, anchor: Position) -> Result<RgbaImage> {
+    let (tile_width, tile_height) = (image.width() as i64, image.height() as i64);
+    let mut canvas = RgbaImage::new(width, height);
+
+    let origin_x = anchor.x.resolve(width as f32 - tile_width as f32).round() as i64;
+    let origin_y = anchor.y.resolve(height as f32 - tile_height as f32).round() as i64;
+    let start_x = origin_x.rem_euclid(tile_width) - tile_width;
+    let start_y = origin_y.rem_euclid(tile_height) - tile_height;
+
+    for y in (start_y..height as i64).step_by(tile_height as usize) {
+        for x in (start_x..width as i64).step_by(tile_width as usize) {
+            imageops::replace(&mut canvas, image, x, y);
+        }
     }
-    Ok(colors)
+
+    Ok(canvas)
 }
 
-/// Interpolate between two colors
-fn interpolate_color(color1: RGBA8, color2: RGBA8, t: f32) -> RGBA8 {
-    let lerp = |a: u8, b: u8, t: f32| -> u8 { (a as f32 * (1.0 - t) + b as f32 * t).round() as u8 };
-    Rgba {
-        r: lerp(color1.r, color2.r, t),
-        g: lerp(color1.g, color2.g, t),
-        b: lerp(color1.b, color2.b, t),
-        a: lerp(color1.a, color2.a, t),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient::Length;
+
+    #[test]
+    fn parses_image_options() {
+        let options = ImageBackgroundOptions::parse("missing.png").unwrap();
+        assert_eq!(options.path, "missing.png");
+        assert_eq!(options.fit, ImageFit::Cover);
+        assert_eq!(options.anchor, Position::default());
+        assert_eq!(options.fill, "black");
+
+        let options = ImageBackgroundOptions::parse(
+            "bg.png, fit=Tile, anchor=top left, fill=white, scale=0.5, blur=8, dim=3",
+        )
+        .unwrap();
+        assert_eq!(options.path, "bg.png");
+        assert_eq!(options.fit, ImageFit::Tile);
+        assert_eq!(
+            options.anchor,
+            Position {
+                x: Length::Percent(0.0),
+                y: Length::Percent(0.0)
+            }
+        );
+        assert_eq!(options.fill, "white");
+        assert_eq!(options.scale, 0.5);
+        assert_eq!(options.blur, 8.0);
+        assert_eq!(options.dim, 1.0, "dim is capped at 1");
+
+        for (fit, expected) in [
+            ("cover", ImageFit::Cover),
+            ("contain", ImageFit::Contain),
+            ("repeat", ImageFit::Tile),
+            ("stretch", ImageFit::Stretch),
+            ("none", ImageFit::Center),
+        ] {
+            let spec = format!("bg.png,fit={}", fit);
+            assert_eq!(ImageBackgroundOptions::parse(&spec).unwrap().fit, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_image_options() {
+        let message = |spec| format!("{}", ImageBackgroundOptions::parse(spec).unwrap_err());
+
+        assert_eq!(
+            message("bg.png,blur=-2"),
+            "Invalid image blur '-2': expected a non-negative number"
+        );
+        assert_eq!(
+            message("bg.png,dim=NaN"),
+            "Invalid image dim 'NaN': expected a non-negative number"
+        );
+        assert_eq!(
+            message("bg.png,scale=0"),
+            "Image tile scale must be positive"
+        );
+        assert_eq!(
+            message("bg.png,fit=fill"),
+            "Unknown image fit 'fill' (expected cover, contain, tile, stretch or center)"
+        );
+        assert_eq!(
+            message("bg.png,tint=red"),
+            "Unknown image option 'tint' (expected fit, anchor, fill, scale, blur or dim)"
+        );
+        assert_eq!(
+            message("bg.png,cover"),
+            "Unexpected value 'cover' in image background"
+        );
+        assert_eq!(
+            message("fit=cover"),
+            "Image background is missing a path: fit=cover"
+        );
+        assert!(message("bg.png,anchor=sideways").starts_with("Invalid image anchor 'sideways'"));
     }
 }
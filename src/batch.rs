@@ -0,0 +1,319 @@
+//! Batch processing of many input images
+
+use anyhow::{anyhow, Result};
+use image::ImageFormat;
+use log::{debug, info};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::FwehError;
+use crate::framer::Framer;
+
+/// Default template used to name batch outputs
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}-framed.{ext}";
+
+/// A single unit of batch work: one input file and where to write its result
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Outcome of a batch run
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Output paths of the images that were framed successfully
+    pub succeeded: Vec<PathBuf>,
+
+    /// Input paths that failed, together with the reason
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl BatchReport {
+    /// Total number of processed jobs
+    pub fn total(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    /// Whether every job succeeded
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Whether the inputs call for batch mode
+///
+/// That is the case for several inputs or any directory or glob pattern, even
+/// if it turns out to hold a single image.
+pub fn is_batch(inputs: &[PathBuf]) -> bool {
+    inputs.len() > 1
+        || inputs
+            .iter()
+            .any(|input| input.is_dir() || is_glob_input(input))
+}
+
+/// Expand a list of input arguments into concrete image files
+///
+/// Each input may be a file, a directory (its supported images are taken, not
+/// recursively) or a glob pattern such as `shots/*.png`. Files in a directory
+/// or glob match that `template` would name as the output of another match,
+/// such as `a-framed.png` next to `a.png`, are results of an earlier run and
+/// are skipped.
+pub fn expand_inputs(inputs: &[PathBuf], template: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+
+        if input.is_dir() {
+            debug!("Expanding directory input: {}", input.display());
+            let mut entries = std::fs::read_dir(input)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file() && is_supported_image(path))
+                .collect::<Vec<_>>();
+            entries.sort();
+            files.extend(skip_outputs(entries, template));
+        } else if is_glob_input(input) {
+            debug!("Expanding glob input: {}", pattern);
+            let mut matches = glob::glob(&pattern)
+                .map_err(|e| FwehError::InvalidParameter(format!("{}: {}", pattern, e)))?
+                .filter_map(|entry| entry.ok())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                return Err(FwehError::InvalidParameter(format!(
+                    "Pattern matched no files: {}",
+                    pattern
+                ))
+                .into());
+            }
+            matches.sort();
+            files.extend(skip_outputs(matches, template));
+        } else {
+            files.push(input.clone());
+        }
+    }
+
+    if files.is_empty() {
+        return Err(FwehError::InvalidParameter("No input images found".to_string()).into());
+    }
+
+    Ok(files)
+}
+
+/// Render an output file name for `input` from a template
+///
+/// Supported placeholders are `{stem}` (file name without extension), `{ext}`
+/// (original extension, `png` if there is none), `{name}` (full file name) and
+/// `{index}` (position of the input in the batch, starting at 1).
+pub fn render_output_name(template: &str, input: &Path, index: usize) -> Result<String> {
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = input
+        .extension()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+    let name = input
+        .file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            FwehError::InvalidParameter(format!("Unclosed placeholder in template: {}", template))
+        })? + start;
+
+        match &rest[start + 1..end] {
+            "stem" => rendered.push_str(&stem),
+            "ext" => rendered.push_str(&ext),
+            "name" => rendered.push_str(&name),
+            "index" => rendered.push_str(&index.to_string()),
+            other => {
+                return Err(FwehError::InvalidParameter(format!(
+                    "Unknown placeholder {{{}}} in template: {}",
+                    other, template
+                ))
+                .into())
+            }
+        }
+
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    if rendered.is_empty() {
+        return Err(anyhow!(
+            "Template rendered an empty file name: {}",
+            template
+        ));
+    }
+
+    Ok(rendered)
+}
+
+/// Plan a batch: pair every input with an output path
+///
+/// Outputs go into `out_dir` when given, otherwise next to their input. Two
+/// inputs resolving to the same output is reported as an error up front rather
+/// than silently overwriting one result with another.
+pub fn plan_batch(
+    inputs: &[PathBuf],
+    out_dir: Option<&Path>,
+    template: &str,
+) -> Result<Vec<BatchJob>> {
+    let mut seen = HashSet::new();
+    let mut jobs = Vec::with_capacity(inputs.len());
+
+    for (i, input) in inputs.iter().enumerate() {
+        let name = render_output_name(template, input, i + 1)?;
+        let dir = match out_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let output = dir.join(name);
+
+        if !seen.insert(output.clone()) {
+            return Err(FwehError::InvalidParameter(format!(
+                "Multiple inputs would be written to {}; add {{index}} to the name template",
+                output.display()
+            ))
+            .into());
+        }
+
+        jobs.push(BatchJob {
+            input: input.clone(),
+            output,
+        });
+    }
+
+    Ok(jobs)
+}
+
+impl Framer {
+    /// Frame every job in parallel, collecting failures instead of aborting
+    pub fn frame_batch(&self, jobs: &[BatchJob]) -> BatchReport {
+        info!("Framing {} images", jobs.len());
+
+        let results = jobs
+            .par_iter()
+            .map(|job| {
+                debug!(
+                    "Framing {} -> {}",
+                    job.input.display(),
+                    job.output.display()
+                );
+                self.frame_file(&job.input, &job.output)
+                    .map_err(|e| (job.input.clone(), e))
+            })
+            .collect::<Vec<_>>();
+
+        let mut report = BatchReport::default();
+        for result in results {
+            match result {
+                Ok(output) => report.succeeded.push(output),
+                Err((input, e)) => {
+                    debug!("Failed to process {}: {}", input.display(), e);
+                    report.failed.push((input, e));
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// Drop the files that `template` would render from another file among them
+fn skip_outputs(files: Vec<PathBuf>, template: &str) -> Vec<PathBuf> {
+    let patterns = files
+        .iter()
+        .filter_map(|file| Some((file, output_pattern(template, file)?)))
+        .collect::<Vec<_>>();
+
+    files
+        .iter()
+        .filter(|file| {
+            let output = patterns.iter().find(|(source, pattern)| {
+                source != file
+                    && source.parent() == file.parent()
+                    && file
+                        .file_name()
+                        .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+            });
+            if let Some((source, _)) = output {
+                debug!(
+                    "Skipping {}, the output of {}",
+                    file.display(),
+                    source.display()
+                );
+            }
+            output.is_none()
+        })
+        .cloned()
+        .collect()
+}
+
+/// A pattern matching every name `template` renders for `input`, whatever its index
+fn output_pattern(template: &str, input: &Path) -> Option<glob::Pattern> {
+    const INDEX: &str = "\u{0}";
+
+    let rendered = render_output_name(&template.replace("{index}", INDEX), input, 0).ok()?;
+    let pattern = rendered
+        .split(INDEX)
+        .map(glob::Pattern::escape)
+        .collect::<Vec<_>>()
+        .join("[0-9]*");
+    glob::Pattern::new(&pattern).ok()
+}
+
+fn is_glob_input(input: &Path) -> bool {
+    !input.exists() && input.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_supported_image(path: &Path) -> bool {
+    ImageFormat::from_path(path)
+        .map(|format| format.can_read())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| Path::new("shots").join(name))
+            .collect()
+    }
+
+    #[test]
+    fn skips_previous_outputs() {
+        let files = paths(&["a.png", "a-framed.png", "b.jpg", "c-framed.png"]);
+        assert_eq!(
+            skip_outputs(files, DEFAULT_NAME_TEMPLATE),
+            paths(&["a.png", "b.jpg", "c-framed.png"])
+        );
+    }
+
+    #[test]
+    fn skips_indexed_outputs() {
+        let files = paths(&["1-a.png", "12-b.png", "a.png", "b.png", "x-b.png"]);
+        assert_eq!(
+            skip_outputs(files, "{index}-{stem}.png"),
+            paths(&["a.png", "b.png", "x-b.png"])
+        );
+    }
+
+    #[test]
+    fn keeps_inputs_renamed_to_themselves() {
+        let files = paths(&["a.png", "b.png"]);
+        assert_eq!(skip_outputs(files.clone(), "{name}"), files);
+    }
+}
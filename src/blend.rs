@@ -0,0 +1,158 @@
+//! Blend modes for compositing layers
+//!
+//! These are the separable blend modes of the W3C Compositing and Blending
+//! spec. A blend function mixes a source color with the backdrop where both
+//! are present; the [`compositor`](crate::compositor) then composites the
+//! result over the backdrop with the source's alpha times its opacity.
+
+use anyhow::{anyhow, Result};
+
+/// How a layer's colors combine with the colors beneath it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// The layer simply covers what is beneath it
+    #[default]
+    Normal,
+
+    /// Darkens by multiplying colors; white is neutral
+    Multiply,
+
+    /// Lightens by multiplying inverted colors; black is neutral
+    Screen,
+
+    /// Multiply or screen depending on the backdrop, boosting contrast
+    Overlay,
+
+    /// A gentler overlay, like shining a diffuse light on the backdrop
+    SoftLight,
+
+    /// Darkens the backdrop and deepens its saturation, like a strong multiply
+    ColorBurn,
+}
+
+impl BlendMode {
+    /// Parse a blend mode name such as `multiply` or `soft-light`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "normal" => Ok(BlendMode::Normal),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "soft-light" | "softlight" => Ok(BlendMode::SoftLight),
+            "color-burn" | "colorburn" => Ok(BlendMode::ColorBurn),
+            other => Err(anyhow!(
+                "Unknown blend mode '{}' (expected normal, multiply, screen, overlay, soft-light or color-burn)",
+                other
+            )),
+        }
+    }
+
+    /// Blend one channel of the backdrop `b` with the source `s` (both 0-1)
+    pub fn blend(self, b: f32, s: f32) -> f32 {
+        match self {
+            BlendMode::Normal => s,
+            BlendMode::Multiply => b * s,
+            BlendMode::Screen => b + s - b * s,
+            BlendMode::Overlay => BlendMode::hard_light(s, b),
+            BlendMode::SoftLight => {
+                if s <= 0.5 {
+                    b - (1.0 - 2.0 * s) * b * (1.0 - b)
+                } else {
+                    let d = if b <= 0.25 {
+                        ((16.0 * b - 12.0) * b + 4.0) * b
+                    } else {
+                        b.sqrt()
+                    };
+                    b + (2.0 * s - 1.0) * (d - b)
+                }
+            }
+            BlendMode::ColorBurn => {
+                if b >= 1.0 {
+                    1.0
+                } else if s <= 0.0 {
+                    0.0
+                } else {
+                    1.0 - ((1.0 - b) / s).min(1.0)
+                }
+            }
+        }
+    }
+
+    /// Hard light, which overlay is with the layers swapped
+    fn hard_light(b: f32, s: f32) -> f32 {
+        if s <= 0.5 {
+            b * 2.0 * s
+        } else {
+            let s = 2.0 * s - 1.0;
+            b + s - b * s
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::SoftLight,
+        BlendMode::ColorBurn,
+    ];
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(BlendMode::parse("Multiply").unwrap(), BlendMode::Multiply);
+        assert_eq!(
+            BlendMode::parse(" soft-light ").unwrap(),
+            BlendMode::SoftLight
+        );
+        assert_eq!(BlendMode::parse("colorburn").unwrap(), BlendMode::ColorBurn);
+        assert!(BlendMode::parse("dodge")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown blend mode 'dodge'"));
+    }
+
+    #[test]
+    fn blends_known_values() {
+        let cases = [
+            (BlendMode::Normal, 0.2, 0.6, 0.6),
+            (BlendMode::Multiply, 0.5, 0.5, 0.25),
+            (BlendMode::Screen, 0.5, 0.5, 0.75),
+            (BlendMode::Overlay, 0.25, 0.8, 0.4),
+            (BlendMode::Overlay, 0.75, 0.2, 0.6),
+            (BlendMode::SoftLight, 0.5, 0.25, 0.375),
+            (BlendMode::SoftLight, 0.25, 0.75, 0.375),
+            (BlendMode::ColorBurn, 0.75, 0.5, 0.5),
+            (BlendMode::ColorBurn, 0.25, 0.5, 0.0),
+        ];
+        for (mode, backdrop, source, expected) in cases {
+            let blended = mode.blend(backdrop, source);
+            assert!(
+                (blended - expected).abs() < 1e-6,
+                "{:?}({}, {}) = {}",
+                mode,
+                backdrop,
+                source,
+                blended
+            );
+        }
+    }
+
+    #[test]
+    fn black_and_white_edges() {
+        for mode in MODES {
+            for value in [0.0, 0.3, 1.0] {
+                let blended = mode.blend(value, value);
+                assert!((0.0..=1.0).contains(&blended), "{:?}", mode);
+            }
+        }
+        assert_eq!(BlendMode::Multiply.blend(0.4, 1.0), 0.4);
+        assert_eq!(BlendMode::Screen.blend(0.4, 0.0), 0.4);
+        assert_eq!(BlendMode::ColorBurn.blend(1.0, 0.0), 1.0);
+        assert_eq!(BlendMode::ColorBurn.blend(0.4, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,172 @@
+//! Fast Gaussian blur for single-channel masks
+//!
+//! Shadows only need their alpha blurred, so this works on a plain `f32`
+//! buffer rather than a full RGBA image. The Gaussian is approximated by three
+//! successive box blurs whose widths are chosen to match the requested
+//! standard deviation. Each box blur is a running sum, so the cost per pixel
+//! does not depend on the radius. Rows are blurred in parallel; columns are
+//! handled by transposing and blurring rows again, which keeps memory access
+//! sequential.
+//!
+//! Samples outside the buffer count as zero, which is what a mask on a
+//! transparent canvas wants.
+
+use rayon::prelude::*;
+
+/// Number of box blurs used to approximate the Gaussian
+const PASSES: usize = 3;
+
+/// Smallest `sigma` that blurs at all, 1/√3
+///
+/// The narrowest blur widens one of the boxes to three pixels, a standard
+/// deviation of about 0.82. Up to 1/√3 rounding to the nearest variance keeps
+/// every box a single pixel wide, so smaller values leave the buffer unchanged.
+pub const MIN_SIGMA: f32 = 0.577_350_3;
+
+/// Blur a `width` x `height` single-channel buffer in place with an
+/// approximate Gaussian of standard deviation `sigma` pixels
+///
+/// A `sigma` of at most [`MIN_SIGMA`] is a no-op.
+pub fn gaussian_blur(values: &mut [f32], width: usize, height: usize, sigma: f32) {
+    assert_eq!(
+        values.len(),
+        width * height,
+        "buffer size must match dimensions"
+    );
+    if width == 0 || height == 0 || !sigma.is_finite() || sigma <= MIN_SIGMA {
+        return;
+    }
+
+    let radii = box_radii(sigma);
+    blur_rows(values, width, &radii);
+    let mut transposed = transpose(values, width, height);
+    blur_rows(&mut transposed, height, &radii);
+    values.copy_from_slice(&transpose(&transposed, height, width));
+}
+
+/// How far the blur of `sigma` spreads a single pixel, in pixels on each side
+///
+/// This is the exact reach of the box approximation, a little over `3 * sigma`.
+pub fn kernel_extent(sigma: f32) -> usize {
+    if !sigma.is_finite() || sigma <= MIN_SIGMA {
+        return 0;
+    }
+    box_radii(sigma).iter().sum()
+}
+
+/// Radii of the box blurs that together approximate a Gaussian of `sigma`
+///
+/// From "Fast Almost-Gaussian Filtering" (Kovesi, 2010): boxes of two
+/// adjacent odd widths, mixed so the summed variance equals `sigma`².
+pub fn box_radii(sigma: f32) -> [usize; PASSES] {
+    let n = PASSES as f32;
+    let variance = 12.0 * sigma * sigma;
+
+    let ideal = (variance / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as i64;
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    let lower = lower.max(1) as f32;
+
+    // How many passes use the lower width
+    let count = ((variance - n * lower * lower - 4.0 * n * lower - 3.0 * n) / (-4.0 * lower - 4.0))
+        .round()
+        .clamp(0.0, n) as usize;
+
+    std::array::from_fn(|pass| {
+        let width = if pass < count { lower } else { lower + 2.0 };
+        (width as usize - 1) / 2
+    })
+}
+
+/// Apply every box blur to each row of the buffer
+fn blur_rows(values: &mut [f32], width: usize, radii: &[usize]) {
+    values.par_chunks_mut(width).for_each(|row| {
+        let mut scratch = vec![0.0; width];
+        for &radius in radii.iter().filter(|&&radius| radius > 0) {
+            box_blur(row, &mut scratch, radius);
+            row.copy_from_slice(&scratch);
+        }
+    });
+}
+
+/// Box blur `source` into `target` with a running sum over `2 * radius + 1` samples
+fn box_blur(source: &[f32], target: &mut [f32], radius: usize) {
+    let length = source.len();
+    let scale = 1.0 / (2 * radius + 1) as f64;
+
+    // Accumulate in f64 so the running sum does not drift along long rows
+    let mut sum: f64 = source[..(radius + 1).min(length)]
+        .iter()
+        .map(|&value| value as f64)
+        .sum();
+    for (i, value) in target.iter_mut().enumerate() {
+        *value = (sum * scale) as f32;
+        if i + radius + 1 < length {
+            sum += source[i + radius + 1] as f64;
+        }
+        if i >= radius {
+            sum -= source[i - radius] as f64;
+        }
+    }
+}
+
+/// Transpose a `width` x `height` buffer into a `height` x `width` one
+fn transpose(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut transposed = vec![0.0; values.len()];
+    transposed
+        .par_chunks_mut(height)
+        .enumerate()
+        .for_each(|(x, column)| {
+            for (y, value) in column.iter_mut().enumerate() {
+                *value = values[y * width + x];
+            }
+        });
+    transposed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Standard deviation of the box blurs for `sigma` combined
+    fn effective_sigma(sigma: f32) -> f32 {
+        let variance: f32 = box_radii(sigma)
+            .iter()
+            .map(|&radius| ((2 * radius + 1).pow(2) - 1) as f32 / 12.0)
+            .sum();
+        variance.sqrt()
+    }
+
+    #[test]
+    fn box_radii_match_sigma() {
+        assert_eq!(box_radii(1.0), [0, 0, 1]);
+        assert_eq!(box_radii(2.0), [1, 1, 2]);
+
+        for sigma in [1.0, 1.5, 2.0, 3.0, 5.0, 10.0, 25.0, 50.0, 100.0] {
+            let effective = effective_sigma(sigma);
+            assert!(
+                (effective - sigma).abs() <= 0.2,
+                "sigma {} blurs like {}",
+                sigma,
+                effective
+            );
+        }
+    }
+
+    #[test]
+    fn small_sigma_is_a_no_op() {
+        assert_eq!(box_radii(MIN_SIGMA), [0, 0, 0]);
+        assert_ne!(box_radii(0.58), [0, 0, 0]);
+        assert_eq!(kernel_extent(MIN_SIGMA), 0);
+
+        let mut values = vec![0.0, 0.0, 1.0, 0.0, 0.0];
+        gaussian_blur(&mut values, 5, 1, MIN_SIGMA);
+        assert_eq!(values, [0.0, 0.0, 1.0, 0.0, 0.0]);
+
+        // One three-pixel box, across the row and down the single-pixel column
+        gaussian_blur(&mut values, 5, 1, 1.0);
+        assert_eq!(values, [0.0, 1.0 / 9.0, 1.0 / 9.0, 1.0 / 9.0, 0.0]);
+    }
+}
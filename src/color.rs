@@ -0,0 +1,690 @@
+//! Color parsing
+//!
+//! Colors can be given as hex codes (`#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`),
+//! as CSS functional notations (`rgb()`, `rgba()`, `hsl()`, `hsla()`, `hwb()`,
+//! `oklab()`, `oklch()`) or by name. Names cover the CSS Color Level 4 keywords and the full
+//! X11 palette bundled in `colors.json`, matched case-insensitively and ignoring
+//! spaces, dashes and underscores. Where the two disagree (e.g. `gray`), the CSS
+//! value wins.
+
+use anyhow::{anyhow, Result};
+use rgb::{Rgba, RGBA8};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// The X11 palette, embedded at compile time
+const X11_COLORS_JSON: &str = include_str!("../colors.json");
+
+/// CSS Color Level 4 named colors
+const CSS_COLORS: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+/// Layout of the bundled `colors.json`
+#[derive(Deserialize)]
+struct ColorTable {
+    colors: HashMap<String, ColorEntry>,
+}
+
+#[derive(Deserialize)]
+struct ColorEntry {
+    #[serde(rename = "Hex")]
+    hex: String,
+}
+
+/// Parse a color string to an RGBA value
+pub fn parse_color(color: &str) -> Result<RGBA8> {
+    let color = color.trim();
+
+    if color.starts_with('#') {
+        parse_hex_color(color)
+    } else if let Some((function, args)) = split_function(color) {
+        parse_functional_color(color, function, args)
+    } else {
+        named_color(color)
+    }
+}
+
+/// Parse a hex color (`#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`)
+fn parse_hex_color(color: &str) -> Result<RGBA8> {
+    let hex = color.trim_start_matches('#');
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid hex color format: {}", color));
+    }
+
+    match hex.len() {
+        6 => {
+            // RGB format
+            let r = u8::from_str_radix(&hex[0..2], 16)?;
+            let g = u8::from_str_radix(&hex[2..4], 16)?;
+            let b = u8::from_str_radix(&hex[4..6], 16)?;
+            Ok(Rgba { r, g, b, a: 255 })
+        }
+        8 => {
+            // RGBA format
+            let r = u8::from_str_radix(&hex[0..2], 16)?;
+            let g = u8::from_str_radix(&hex[2..4], 16)?;
+            let b = u8::from_str_radix(&hex[4..6], 16)?;
+            let a = u8::from_str_radix(&hex[6..8], 16)?;
+            Ok(Rgba { r, g, b, a })
+        }
+        3 => {
+            // Short RGB format
+            let r = u8::from_str_radix(&hex[0..1], 16)? * 17;
+            let g = u8::from_str_radix(&hex[1..2], 16)? * 17;
+            let b = u8::from_str_radix(&hex[2..3], 16)? * 17;
+            Ok(Rgba { r, g, b, a: 255 })
+        }
+        4 => {
+            // Short RGBA format
+            let r = u8::from_str_radix(&hex[0..1], 16)? * 17;
+            let g = u8::from_str_radix(&hex[1..2], 16)? * 17;
+            let b = u8::from_str_radix(&hex[2..3], 16)? * 17;
+            let a = u8::from_str_radix(&hex[3..4], 16)? * 17;
+            Ok(Rgba { r, g, b, a })
+        }
+        _ => Err(anyhow!("Invalid hex color format: {}", color)),
+    }
+}
+
+/// Split `name(args)` into its function name and argument list
+fn split_function(color: &str) -> Option<(&str, &str)> {
+    let open = color.find('(')?;
+    let args = color[open + 1..].strip_suffix(')')?;
+    Some((color[..open].trim(), args))
+}
+
+/// Parse a CSS functional color notation such as `hsl(220 15% 20% / 50%)`
+fn parse_functional_color(color: &str, function: &str, args: &str) -> Result<RGBA8> {
+    let function = function.to_ascii_lowercase();
+    let (components, alpha) = split_components(args)
+        .map_err(|e| anyhow!("Invalid {}() color '{}': {}", function, color, e))?;
+
+    let component_error =
+        |e: anyhow::Error| anyhow!("Invalid {}() color '{}': {}", function, color, e);
+
+    if components.len() != 3 {
+        return Err(component_error(anyhow!(
+            "expected 3 components, got {}",
+            components.len()
+        )));
+    }
+
+    let [c0, c1, c2] = [components[0], components[1], components[2]];
+    let (r, g, b) = match function.as_str() {
+        "rgb" | "rgba" => (
+            parse_rgb_channel(c0, "red").map_err(component_error)?,
+            parse_rgb_channel(c1, "green").map_err(component_error)?,
+            parse_rgb_channel(c2, "blue").map_err(component_error)?,
+        ),
+        "hsl" | "hsla" => {
+            let h = parse_angle(c0, "hue")
+                .map_err(component_error)?
+                .rem_euclid(360.0);
+            let s = parse_fraction(c1, "saturation", 100.0).map_err(component_error)?;
+            let l = parse_fraction(c2, "lightness", 100.0).map_err(component_error)?;
+            hsl_to_rgb(h, s, l)
+        }
+        "hwb" => {
+            let h = parse_angle(c0, "hue")
+                .map_err(component_error)?
+                .rem_euclid(360.0);
+            let w = parse_fraction(c1, "whiteness", 100.0).map_err(component_error)?;
+            let b = parse_fraction(c2, "blackness", 100.0).map_err(component_error)?;
+            hwb_to_rgb(h, w, b)
+        }
+        "oklab" => {
+            let l = parse_fraction(c0, "lightness", 1.0).map_err(component_error)?;
+            let a = parse_scaled(c1, "a", 0.4).map_err(component_error)?;
+            let b = parse_scaled(c2, "b", 0.4).map_err(component_error)?;
+            oklab_to_srgb(l, a, b)
+        }
+        "oklch" => {
+            let l = parse_fraction(c0, "lightness", 1.0).map_err(component_error)?;
+            let c = parse_scaled(c1, "chroma", 0.4).map_err(component_error)?;
+            let h = parse_angle(c2, "hue")
+                .map_err(component_error)?
+                .to_radians();
+            oklab_to_srgb(l, c * h.cos(), c * h.sin())
+        }
+        other => return Err(anyhow!("Unknown color function: {}()", other)),
+    };
+
+    let a = match alpha {
+        Some(alpha) => parse_alpha(alpha).map_err(component_error)?,
+        None => 1.0,
+    };
+
+    Ok(to_rgba8(r, g, b, a))
+}
+
+/// Split function arguments into color components and an optional alpha
+///
+/// Both the modern space-separated syntax (`10 20 30 / 50%`) and the legacy
+/// comma-separated one (`10, 20, 30, 0.5`) are accepted.
+fn split_components(args: &str) -> Result<(Vec<&str>, Option<&str>)> {
+    if args.contains(',') {
+        if args.contains('/') {
+            return Err(anyhow!("cannot mix commas with a '/' alpha separator"));
+        }
+
+        let mut components = args.split(',').map(str::trim).collect::<Vec<_>>();
+        if components.iter().any(|c| c.is_empty()) {
+            return Err(anyhow!("empty component"));
+        }
+
+        let alpha = if components.len() == 4 {
+            components.pop()
+        } else {
+            None
+        };
+        return Ok((components, alpha));
+    }
+
+    let (main, alpha) = match args.split_once('/') {
+        Some((main, alpha)) => {
+            let alpha = alpha.trim();
+            if alpha.is_empty() || alpha.contains('/') {
+                return Err(anyhow!("expected a single alpha value after '/'"));
+            }
+            (main, Some(alpha))
+        }
+        None => (args, None),
+    };
+
+    Ok((main.split_whitespace().collect(), alpha))
+}
+
+/// Parse an sRGB channel: a number in 0-255 or a percentage
+fn parse_rgb_channel(value: &str, label: &str) -> Result<f32> {
+    if let Some(percent) = value.strip_suffix('%') {
+        let percent = parse_number(percent, label, value)?;
+        return Ok((percent / 100.0).clamp(0.0, 1.0));
+    }
+
+    let number = parse_number(value, label, value)?;
+    Ok((number / 255.0).clamp(0.0, 1.0))
+}
+
+/// Parse a value in 0-1, given as a percentage or as a number over `number_range`
+fn parse_fraction(value: &str, label: &str, number_range: f32) -> Result<f32> {
+    if let Some(percent) = value.strip_suffix('%') {
+        let percent = parse_number(percent, label, value)?;
+        return Ok((percent / 100.0).clamp(0.0, 1.0));
+    }
+
+    let number = parse_number(value, label, value)?;
+    Ok((number / number_range).clamp(0.0, 1.0))
+}
+
+/// Parse a signed value where 100% corresponds to `full_scale`
+fn parse_scaled(value: &str, label: &str, full_scale: f32) -> Result<f32> {
+    if let Some(percent) = value.strip_suffix('%') {
+        let percent = parse_number(percent, label, value)?;
+        return Ok(percent / 100.0 * full_scale);
+    }
+
+    parse_number(value, label, value)
+}
+
+/// Parse an alpha value: a number in 0-1 or a percentage
+fn parse_alpha(value: &str) -> Result<f32> {
+    parse_fraction(value, "alpha", 1.0)
+}
+
+/// Parse an angle (e.g. a hue) in degrees; `deg`, `rad`, `grad` and `turn` units are accepted
+///
+/// The angle is not normalized, so `360deg` and `0deg` stay distinct.
+pub(crate) fn parse_angle(value: &str, label: &str) -> Result<f32> {
+    let lower = value.to_ascii_lowercase();
+    let (number, degrees_per_unit) = if let Some(n) = lower.strip_suffix("deg") {
+        (n, 1.0)
+    } else if let Some(n) = lower.strip_suffix("grad") {
+        (n, 0.9)
+    } else if let Some(n) = lower.strip_suffix("rad") {
+        (n, 180.0 / std::f32::consts::PI)
+    } else if let Some(n) = lower.strip_suffix("turn") {
+        (n, 360.0)
+    } else {
+        (lower.as_str(), 1.0)
+    };
+
+    Ok(parse_number(number, label, value)? * degrees_per_unit)
+}
+
+/// Parse a plain number, naming the offending component on failure
+fn parse_number(number: &str, label: &str, raw: &str) -> Result<f32> {
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| anyhow!("{} component '{}' is not a valid number", label, raw))
+}
+
+/// Convert HSL (hue in degrees, saturation and lightness in 0-1) to sRGB
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let f = |n: f32| {
+        let k = (n + h / 30.0) % 12.0;
+        let a = s * l.min(1.0 - l);
+        l - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (f(0.0), f(8.0), f(4.0))
+}
+
+/// Convert HWB (hue in degrees, whiteness and blackness in 0-1) to sRGB
+fn hwb_to_rgb(h: f32, w: f32, b: f32) -> (f32, f32, f32) {
+    if w + b >= 1.0 {
+        let gray = w / (w + b);
+        return (gray, gray, gray);
+    }
+
+    let (r, g, bl) = hsl_to_rgb(h, 1.0, 0.5);
+    let scale = 1.0 - w - b;
+    (r * scale + w, g * scale + w, bl * scale + w)
+}
+
+/// Convert Oklab to gamma-encoded sRGB (components may fall outside 0-1)
+pub(crate) fn oklab_to_srgb(l: f32, a: f32, b: f32) -> (f32, f32, f32) {
+    let (r, g, b) = oklab_to_linear_srgb(l, a, b);
+    (linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+}
+
+/// Convert Oklab to linear-light sRGB
+pub(crate) fn oklab_to_linear_srgb(l: f32, a: f32, b: f32) -> (f32, f32, f32) {
+    let l_ = l + 0.396_337_78 * a + 0.215_803_76 * b;
+    let m_ = l - 0.105_561_346 * a - 0.063_854_17 * b;
+    let s_ = l - 0.089_484_18 * a - 1.291_485_5 * b;
+
+    let (l, m, s) = (l_.powi(3), m_.powi(3), s_.powi(3));
+
+    (
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    )
+}
+
+/// Convert linear-light sRGB to Oklab
+pub(crate) fn linear_srgb_to_oklab(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let l = 0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b;
+    let m = 0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b;
+    let s = 0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b;
+
+    let (l_, m_, s_) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+    (
+        0.210_454_26 * l_ + 0.793_617_8 * m_ - 0.004_072_047 * s_,
+        1.977_998_5 * l_ - 2.428_592_2 * m_ + 0.450_593_7 * s_,
+        0.025_904_037 * l_ + 0.782_771_77 * m_ - 0.808_675_77 * s_,
+    )
+}
+
+/// Apply the inverse sRGB transfer function to a gamma-encoded value
+pub(crate) fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Apply the sRGB transfer function to a linear-light value
+pub(crate) fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Quantize a 0-1 value to 8 bits with ordered dithering at pixel (`x`, `y`)
+///
+/// The threshold comes from interleaved gradient noise, which spreads the
+/// rounding error evenly and hides the banding of smooth 8-bit ramps. Values
+/// within float error of an 8-bit level, such as colors that went through a
+/// round trip to linear light, are left on that level.
+pub(crate) fn quantize_dithered(value: f32, x: u32, y: u32) -> u8 {
+    let scaled = value.clamp(0.0, 1.0) * 255.0;
+    let level = scaled.round();
+    if (scaled - level).abs() < 1e-3 {
+        return level as u8;
+    }
+
+    let noise = (52.982_918 * (0.067_110_56 * x as f32 + 0.005_837_15 * y as f32).fract()).fract();
+    (scaled + noise - 0.5).round().clamp(0.0, 255.0) as u8
+}
+
+/// Quantize 0-1 float components to 8-bit RGBA, clipping out-of-gamut values
+pub(crate) fn to_rgba8(r: f32, g: f32, b: f32, a: f32) -> RGBA8 {
+    let quantize = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgba {
+        r: quantize(r),
+        g: quantize(g),
+        b: quantize(b),
+        a: quantize(a),
+    }
+}
+
+/// Look up a named color, suggesting the closest name when it is unknown
+fn named_color(name: &str) -> Result<RGBA8> {
+    let key = normalize_name(name);
+
+    if key == "transparent" {
+        return Ok(Rgba {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        });
+    }
+
+    if let Some(color) = named_colors().get(&key) {
+        return Ok(*color);
+    }
+
+    match suggest_color_name(&key) {
+        Some(suggestion) => Err(anyhow!(
+            "Unknown color name: {} (did you mean '{}'?)",
+            name,
+            suggestion
+        )),
+        None => Err(anyhow!("Unknown color name: {}", name)),
+    }
+}
+
+/// All named colors, keyed by normalized name
+fn named_colors() -> &'static HashMap<String, RGBA8> {
+    static NAMED_COLORS: OnceLock<HashMap<String, RGBA8>> = OnceLock::new();
+
+    NAMED_COLORS.get_or_init(|| {
+        let table: ColorTable =
+            serde_json::from_str(X11_COLORS_JSON).expect("bundled colors.json must be valid");
+
+        let mut colors = table
+            .colors
+            .iter()
+            .filter_map(|(name, entry)| {
+                parse_hex_color(&entry.hex)
+                    .ok()
+                    .map(|color| (normalize_name(name), color))
+            })
+            .collect::<HashMap<_, _>>();
+
+        // CSS keywords take precedence over X11 entries with the same name
+        colors.extend(CSS_COLORS.iter().map(|(name, [r, g, b])| {
+            (
+                name.to_string(),
+                Rgba {
+                    r: *r,
+                    g: *g,
+                    b: *b,
+                    a: 255,
+                },
+            )
+        }));
+
+        colors
+    })
+}
+
+/// Find the known color name closest to `key`, if any is reasonably close
+fn suggest_color_name(key: &str) -> Option<&'static str> {
+    let max_distance = (key.len() / 3).max(2);
+
+    named_colors()
+        .keys()
+        .map(|name| (strsim::levenshtein(key, name), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)))
+        .map(|(_, name)| name.as_str())
+}
+
+/// Lowercase a color name and drop separators, so `Light Coral` matches `LightCoral`
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(color: &str) -> [u8; 4] {
+        let color = parse_color(color).unwrap();
+        [color.r, color.g, color.b, color.a]
+    }
+
+    fn error(color: &str) -> String {
+        parse_color(color).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_hex_lengths() {
+        assert_eq!(parse_color("#f80").unwrap(), RGBA8::new(255, 136, 0, 255));
+        assert_eq!(parse_color("#f808").unwrap(), RGBA8::new(255, 136, 0, 136));
+        assert_eq!(
+            parse_color("#ff8800").unwrap(),
+            RGBA8::new(255, 136, 0, 255)
+        );
+        assert_eq!(
+            parse_color("#ff880080").unwrap(),
+            RGBA8::new(255, 136, 0, 128)
+        );
+        assert!(parse_color("#ff88").is_ok());
+        assert!(parse_color("#ff880").is_err());
+    }
+
+    #[test]
+    fn parses_rgb() {
+        assert_eq!(rgba("rgb(255 128 0)"), [255, 128, 0, 255]);
+        assert_eq!(rgba("rgb(100%, 50%, 0%)"), [255, 128, 0, 255]);
+        assert_eq!(rgba("rgba(0, 0, 255, 0.5)"), [0, 0, 255, 128]);
+        assert_eq!(rgba("RGB(0 0 0 / 25%)"), [0, 0, 0, 64]);
+    }
+
+    #[test]
+    fn parses_hsl_and_hwb() {
+        assert_eq!(rgba("hsl(120 100% 50%)"), [0, 255, 0, 255]);
+        assert_eq!(rgba("hsl(0.5turn 100% 50%)"), [0, 255, 255, 255]);
+        assert_eq!(rgba("hsla(240, 100%, 25%, 0.5)"), [0, 0, 128, 128]);
+        assert_eq!(rgba("hwb(0 0% 0%)"), [255, 0, 0, 255]);
+        assert_eq!(rgba("hwb(0 50% 50%)"), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn parses_oklab_and_oklch() {
+        assert_eq!(rgba("oklab(1 0 0)"), [255, 255, 255, 255]);
+        assert_eq!(rgba("oklab(0% 0 0)"), [0, 0, 0, 255]);
+
+        // CSS red, within a rounding step
+        let [r, g, b, a] = rgba("oklch(0.628 0.2577 29.23deg / 0.5)");
+        assert!(r >= 254 && g <= 1 && b <= 1, "{:?}", (r, g, b));
+        assert_eq!(a, 128);
+    }
+
+    #[test]
+    fn names_bad_components() {
+        assert_eq!(
+            error("rgb(0 0 x)"),
+            "Invalid rgb() color 'rgb(0 0 x)': blue component 'x' is not a valid number"
+        );
+        assert_eq!(
+            error("hsl(10 20%)"),
+            "Invalid hsl() color 'hsl(10 20%)': expected 3 components, got 2"
+        );
+        assert!(error("rgb(0, 0, 0 / 1)").contains("cannot mix commas"));
+        assert!(error("rgb(0 0 0 / 1 / 2)").contains("single alpha value"));
+        assert!(error("hsl(foo 20% 30%)").contains("hue component 'foo'"));
+        assert_eq!(error("lab(50 0 0)"), "Unknown color function: lab()");
+        assert!(error("#12345g").starts_with("Invalid hex color format"));
+    }
+
+    #[test]
+    fn looks_up_css_and_x11_names() {
+        assert_eq!(rgba("green"), [0, 128, 0, 255]);
+        assert_eq!(rgba("lime"), [0, 255, 0, 255]);
+        assert_eq!(rgba("Light Coral"), [240, 128, 128, 255]);
+        assert_eq!(rgba("rosy-brown1"), [255, 193, 193, 255]);
+        assert_eq!(rgba("transparent"), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        assert_eq!(
+            error("gren"),
+            "Unknown color name: gren (did you mean 'green'?)"
+        );
+        assert_eq!(error("qqqqqqqqqqqq"), "Unknown color name: qqqqqqqqqqqq");
+    }
+}
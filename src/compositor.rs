@@ -0,0 +1,330 @@
+//! Alpha-correct, linear-light compositing
+//!
+//! Images are stored as 8-bit sRGB with straight (unpremultiplied) alpha, but
+//! mixing them in that form lets the color hidden under transparent pixels
+//! bleed into soft edges and makes translucent dark colors look muddy. The
+//! compositor converts pixels to linear-light, premultiplied `f32`, applies a
+//! blend mode and the Porter-Duff "over" operator there, and converts the
+//! result back to sRGB with dithering so smooth falloffs such as shadow edges
+//! do not band.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+use crate::blend::BlendMode;
+use crate::color::{linear_to_srgb, quantize_dithered, srgb_to_linear};
+
+/// Convert a straight-alpha sRGB pixel to premultiplied linear-light components
+pub fn to_linear(pixel: [u8; 4]) -> [f32; 4] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)));
+
+    let alpha = pixel[3] as f32 / 255.0;
+    [
+        table[pixel[0] as usize] * alpha,
+        table[pixel[1] as usize] * alpha,
+        table[pixel[2] as usize] * alpha,
+        alpha,
+    ]
+}
+
+/// Convert premultiplied linear-light components back to a straight-alpha sRGB pixel
+///
+/// Color channels are dithered at pixel (`x`, `y`). Fully transparent pixels
+/// come out as transparent black.
+pub fn from_linear(pixel: [f32; 4], x: u32, y: u32) -> [u8; 4] {
+    let alpha = pixel[3].clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+
+    let channel =
+        |value: f32| quantize_dithered(linear_to_srgb((value / alpha).clamp(0.0, 1.0)), x, y);
+    [
+        channel(pixel[0]),
+        channel(pixel[1]),
+        channel(pixel[2]),
+        (alpha * 255.0).round() as u8,
+    ]
+}
+
+/// Blend premultiplied `source` onto premultiplied `backdrop`
+///
+/// This is the W3C compositing formula: where both are present the colors
+/// are mixed by `mode`, and the result is composited with source-over.
+pub fn blend_pixel(backdrop: [f32; 4], source: [f32; 4], mode: BlendMode) -> [f32; 4] {
+    let (ab, as_) = (backdrop[3], source[3]);
+    let mut result = [0.0, 0.0, 0.0, as_ + ab * (1.0 - as_)];
+
+    for channel in 0..3 {
+        let (cb, cs) = (backdrop[channel], source[channel]);
+        let mixed = if mode == BlendMode::Normal || ab <= 0.0 || as_ <= 0.0 {
+            cs * ab
+        } else {
+            // Blend functions work on unpremultiplied colors
+            as_ * ab * mode.blend(cb / ab, cs / as_)
+        };
+        result[channel] = cs * (1.0 - ab) + cb * (1.0 - as_) + mixed;
+    }
+
+    result
+}
+
+/// Composite `top` onto `bottom` in place with its top left corner at (`x`, `y`)
+///
+/// The colors are mixed with `mode` and `top` is faded by `opacity` (0.0-1.0).
+/// Parts of `top` outside `bottom` are clipped, so the position may be negative.
+pub fn composite(
+    bottom: &mut RgbaImage,
+    top: &RgbaImage,
+    x: i64,
+    y: i64,
+    mode: BlendMode,
+    opacity: f32,
+) {
+    let (bottom_width, bottom_height) = (bottom.width() as i64, bottom.height() as i64);
+    let (top_width, top_height) = (top.width() as i64, top.height() as i64);
+
+    // Intersection of the two images in bottom coordinates
+    let (left, right) = (x.max(0), x.saturating_add(top_width).min(bottom_width));
+    let (upper, lower) = (y.max(0), y.saturating_add(top_height).min(bottom_height));
+    if left >= right || upper >= lower || opacity <= 0.0 {
+        return;
+    }
+
+    let opaque = mode == BlendMode::Normal && opacity >= 1.0;
+    let row_length = bottom_width as usize * 4;
+    bottom
+        .par_chunks_mut(row_length)
+        .enumerate()
+        .skip(upper as usize)
+        .take((lower - upper) as usize)
+        .for_each(|(row, pixels)| {
+            let top_row = (row as i64 - y) as u32;
+            for column in left..right {
+                let source = top.get_pixel((column - x) as u32, top_row).0;
+                if source[3] == 0 {
+                    continue;
+                }
+
+                let index = column as usize * 4;
+                let backdrop = &mut pixels[index..index + 4];
+                if opaque && source[3] == 255 {
+                    backdrop.copy_from_slice(&source);
+                    continue;
+                }
+
+                let current = [backdrop[0], backdrop[1], backdrop[2], backdrop[3]];
+                let source = to_linear(source).map(|value| value * opacity.min(1.0));
+                let mixed = blend_pixel(to_linear(current), source, mode);
+                backdrop.copy_from_slice(&from_linear(mixed, column as u32, row as u32));
+            }
+        });
+}
+
+/// Composite `top` over `bottom` in place with its top left corner at (`x`, `y`)
+pub fn over(bottom: &mut RgbaImage, top: &RgbaImage, x: i64, y: i64) {
+    composite(bottom, top, x, y, BlendMode::Normal, 1.0);
+}
+
+/// Composite a layer the size of `base` onto it with a blend mode and opacity
+pub fn blend_layer(base: &mut RgbaImage, layer: &RgbaImage, mode: BlendMode, opacity: f32) {
+    debug_assert_eq!(base.dimensions(), layer.dimensions());
+    composite(base, layer, 0, 0, mode, opacity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Composite a single `top` pixel onto a single `bottom` pixel at (`x`, `y`)
+    fn mix(bottom: [u8; 4], top: [u8; 4], mode: BlendMode, opacity: f32) -> [u8; 4] {
+        let mut canvas = RgbaImage::from_pixel(4, 4, image::Rgba(bottom));
+        let layer = RgbaImage::from_pixel(4, 4, image::Rgba(top));
+        composite(&mut canvas, &layer, 0, 0, mode, opacity);
+        canvas.get_pixel(1, 2).0
+    }
+
+    /// An sRGB byte for a linear-light value, give or take the dither
+    fn assert_near(actual: u8, linear: f32) {
+        let expected = linear_to_srgb(linear) * 255.0;
+        assert!(
+            (actual as f32 - expected).abs() <= 1.0,
+            "{} is not about {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn opaque_channels_survive_the_round_trip() {
+        for alpha in [255, 128, 1] {
+            for value in 0..=255 {
+                let pixel = [value, 255 - value, value / 2, alpha];
+                for (x, y) in [(0, 0), (1, 0), (3, 7), (100, 41)] {
+                    assert_eq!(from_linear(to_linear(pixel), x, y), pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn translucent_layer_keeps_matching_colors() {
+        let mut canvas = RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        let layer = RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 128]));
+        over(&mut canvas, &layer, 0, 0);
+        assert_eq!(canvas.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn normal_mixes_in_linear_light() {
+        let white = [255, 255, 255, 255];
+
+        // Half black over white is half the light, not half the byte value
+        let mixed = mix(white, [0, 0, 0, 128], BlendMode::Normal, 1.0);
+        assert_near(mixed[0], 1.0 - 128.0 / 255.0);
+        assert_eq!(mixed[3], 255);
+
+        // Layer opacity fades the source like its alpha does
+        let faded = mix(white, [0, 0, 0, 255], BlendMode::Normal, 128.0 / 255.0);
+        assert_eq!(faded, mixed);
+    }
+
+    #[test]
+    fn normal_over_transparent_keeps_straight_color() {
+        let clear = [0, 0, 0, 0];
+        assert_eq!(
+            mix(clear, [200, 100, 50, 128], BlendMode::Normal, 1.0),
+            [200, 100, 50, 128]
+        );
+
+        // Two half-transparent layers of one color stack up to 75% alpha
+        let stacked = mix(
+            [200, 100, 50, 128],
+            [200, 100, 50, 128],
+            BlendMode::Normal,
+            1.0,
+        );
+        assert_eq!(&stacked[..3], [200, 100, 50]);
+        assert_eq!(stacked[3], 192);
+    }
+
+    #[test]
+    fn opaque_sources_are_copied() {
+        let top = [1, 2, 3, 255];
+        assert_eq!(mix([250, 250, 250, 255], top, BlendMode::Normal, 1.0), top);
+        assert_eq!(mix([0, 0, 0, 0], top, BlendMode::Normal, 1.0), top);
+
+        // Fully transparent sources and zero opacity leave the backdrop alone
+        let bottom = [10, 20, 30, 40];
+        assert_eq!(mix(bottom, [0, 0, 0, 0], BlendMode::Normal, 1.0), bottom);
+        assert_eq!(mix(bottom, top, BlendMode::Normal, 0.0), bottom);
+    }
+
+    #[test]
+    fn blend_modes_on_opaque_layers() {
+        let (red, grey) = ([255, 0, 0, 255], [128, 128, 128, 255]);
+        let (white, black) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+        let grey_linear = to_linear(grey)[0];
+
+        assert_eq!(mix(red, grey, BlendMode::Normal, 1.0), grey);
+
+        // Multiply: white is neutral and black absorbs
+        assert_eq!(mix(red, grey, BlendMode::Multiply, 1.0), [128, 0, 0, 255]);
+        assert_eq!(mix(white, grey, BlendMode::Multiply, 1.0), grey);
+        assert_eq!(mix(grey, black, BlendMode::Multiply, 1.0), black);
+
+        // Screen: black is neutral and white saturates
+        assert_eq!(
+            mix(red, [0, 255, 0, 255], BlendMode::Screen, 1.0),
+            [255, 255, 0, 255]
+        );
+        assert_eq!(mix(black, grey, BlendMode::Screen, 1.0), grey);
+        let screened = mix(grey, grey, BlendMode::Screen, 1.0);
+        assert_near(screened[0], 2.0 * grey_linear - grey_linear * grey_linear);
+
+        // Overlay keeps black and white backdrops and doubles dark ones
+        assert_eq!(mix(white, grey, BlendMode::Overlay, 1.0), white);
+        assert_eq!(mix(black, grey, BlendMode::Overlay, 1.0), black);
+        assert_near(
+            mix(grey, white, BlendMode::Overlay, 1.0)[0],
+            2.0 * grey_linear,
+        );
+
+        // Color burn: a white source or backdrop leaves the backdrop as is
+        assert_eq!(mix(grey, white, BlendMode::ColorBurn, 1.0), grey);
+        assert_eq!(mix(white, grey, BlendMode::ColorBurn, 1.0), white);
+        assert_eq!(mix(grey, black, BlendMode::ColorBurn, 1.0), black);
+        let light = [240, 240, 240, 255];
+        let light_linear = to_linear(light)[0];
+        assert_near(
+            mix(light, grey, BlendMode::ColorBurn, 1.0)[0],
+            1.0 - (1.0 - light_linear) / grey_linear,
+        );
+    }
+
+    #[test]
+    fn blend_modes_on_translucent_layers() {
+        let white = [255, 255, 255, 255];
+        let grey_linear = to_linear([128, 128, 128, 255])[0];
+
+        // A half-transparent source mixes the blended color with the backdrop
+        for mode in [
+            BlendMode::Multiply,
+            BlendMode::Overlay,
+            BlendMode::ColorBurn,
+        ] {
+            let blended = mode.blend(1.0, grey_linear);
+            let mixed = mix(white, [128, 128, 128, 128], mode, 1.0);
+            let alpha = 128.0 / 255.0;
+            assert_near(mixed[0], (1.0 - alpha) + alpha * blended);
+            assert_eq!(mixed[3], 255);
+        }
+
+        // Opacity fades a blended layer the same way
+        let faded = mix(white, [0, 0, 0, 255], BlendMode::Multiply, 0.25);
+        assert_near(faded[0], 0.75);
+
+        // Over a transparent backdrop there is nothing to blend with
+        let clear = [0, 0, 0, 0];
+        for mode in [BlendMode::Multiply, BlendMode::Screen, BlendMode::ColorBurn] {
+            assert_eq!(
+                mix(clear, [200, 100, 50, 128], mode, 1.0),
+                [200, 100, 50, 128]
+            );
+        }
+
+        // Where the backdrop is half covered, half the source stays unblended
+        let mixed = mix(
+            [0, 0, 0, 128],
+            [255, 255, 255, 255],
+            BlendMode::Multiply,
+            1.0,
+        );
+        assert_eq!(mixed[3], 255);
+        assert_near(mixed[0], 1.0 - 128.0 / 255.0);
+    }
+
+    #[test]
+    fn clips_layers_outside_the_canvas() {
+        let mut canvas = RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]));
+        let layer = RgbaImage::from_pixel(3, 3, image::Rgba([255, 255, 255, 255]));
+        composite(&mut canvas, &layer, -2, 3, BlendMode::Normal, 1.0);
+        composite(
+            &mut canvas,
+            &layer,
+            i64::MAX,
+            i64::MIN,
+            BlendMode::Normal,
+            1.0,
+        );
+
+        let lit = canvas
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] == 255)
+            .map(|(x, y, _)| (x, y))
+            .collect::<Vec<_>>();
+        assert_eq!(lit, [(0, 3)]);
+    }
+}
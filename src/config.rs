@@ -0,0 +1,460 @@
+//! Config file and named presets
+//!
+//! Presets are read from a user-level `config.toml` (e.g. `~/.config/fweh/config.toml`)
+//! and a project-local `fweh.toml` in the working directory. A project preset
+//! replaces a user preset of the same name.
+//!
+//! ```toml
+//! [presets.docs-dark]
+//! background = "grad:#1e1e2e-#313244"
+//! roundness = 4
+//! ratio = "16:9"
+//!
+//! [presets.docs-dark.shadow]
+//! offset = "0,12"
+//! radius = 30
+//! opacity = 0.5
+//! blend = "multiply"
+//!
+//! [presets.docs-card]
+//! background = "colr:#f4f4f5"
+//! elevation = 3
+//!
+//! [presets.docs-light]
+//! background = ["colr:white", "patt:dots;opacity=0.5"]
+//! shadows = ["0 1 3 #0000001f", "0 12 40 #00000026"]
+//! ```
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the project-local config file
+pub const PROJECT_CONFIG_FILE: &str = "fweh.toml";
+
+/// Contents of a config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named presets
+    #[serde(default)]
+    pub presets: BTreeMap<String, Preset>,
+}
+
+/// A named set of processing options
+///
+/// Every field is optional so that presets and command line flags can be
+/// layered on top of each other field by field.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preset {
+    pub scale: Option<f32>,
+    pub roundness: Option<f32>,
+    pub offset: Option<String>,
+
+    /// A single background, or a list of layers from bottom to top
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub background: Option<Vec<String>>,
+    pub noise: Option<String>,
+    pub ratio: Option<String>,
+    pub shadow: Option<ShadowPreset>,
+
+    /// Stacked shadows in `--shadow` syntax, from top to bottom
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub shadows: Option<Vec<String>>,
+
+    /// An elevation level (`elevation = 3`) or name (`elevation = "modal"`)
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub elevation: Option<String>,
+}
+
+/// Shadow part of a preset
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ShadowPreset {
+    /// Whether the shadow is drawn; a `[shadow]` section enables it unless set to false
+    #[serde(default = "enabled_by_section")]
+    pub enabled: Option<bool>,
+    pub offset: Option<String>,
+    pub color: Option<String>,
+    pub radius: Option<f32>,
+    pub opacity: Option<f32>,
+    pub spread: Option<f32>,
+    pub blend: Option<String>,
+}
+
+impl Preset {
+    /// Fill every unset field from `fallback`
+    pub fn or(self, fallback: Preset) -> Preset {
+        Preset {
+            scale: self.scale.or(fallback.scale),
+            roundness: self.roundness.or(fallback.roundness),
+            offset: self.offset.or(fallback.offset),
+            background: self.background.or(fallback.background),
+            noise: self.noise.or(fallback.noise),
+            ratio: self.ratio.or(fallback.ratio),
+            shadow: match (self.shadow, fallback.shadow) {
+                (Some(shadow), Some(fallback)) => Some(shadow.or(fallback)),
+                (shadow, fallback) => shadow.or(fallback),
+            },
+            shadows: self.shadows.or(fallback.shadows),
+            elevation: self.elevation.or(fallback.elevation),
+        }
+    }
+}
+
+impl ShadowPreset {
+    /// Fill every unset field from `fallback`
+    pub fn or(self, fallback: ShadowPreset) -> ShadowPreset {
+        ShadowPreset {
+            enabled: self.enabled.or(fallback.enabled),
+            offset: self.offset.or(fallback.offset),
+            color: self.color.or(fallback.color),
+            radius: self.radius.or(fallback.radius),
+            opacity: self.opacity.or(fallback.opacity),
+            spread: self.spread.or(fallback.spread),
+            blend: self.blend.or(fallback.blend),
+        }
+    }
+}
+
+impl Config {
+    /// Look up a preset by name
+    pub fn preset(&self, name: &str) -> Result<Preset> {
+        self.presets.get(name).cloned().ok_or_else(|| {
+            if self.presets.is_empty() {
+                anyhow!("Unknown preset '{}': no presets are configured", name)
+            } else {
+                let available = self.presets.keys().cloned().collect::<Vec<_>>();
+                anyhow!(
+                    "Unknown preset '{}' (available: {})",
+                    name,
+                    available.join(", ")
+                )
+            }
+        })
+    }
+
+    /// Merge `other` into this config, replacing presets with the same name
+    fn extend(&mut self, other: Config) {
+        self.presets.extend(other.presets);
+    }
+}
+
+fn enabled_by_section() -> Option<bool> {
+    Some(true)
+}
+
+/// Accept either a single string or a list of strings
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    }))
+}
+
+/// Accept either a number or a string, as a string
+fn number_or_string<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(i64),
+        String(String),
+    }
+
+    Ok(Some(match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(value) => value.to_string(),
+        NumberOrString::String(value) => value,
+    }))
+}
+
+/// Load the config from `path`, or from the user and project config files
+pub fn load_config(path: Option<&Path>) -> Result<Config> {
+    if let Some(path) = path {
+        return read_config(path);
+    }
+
+    let paths = [user_config_path(), Some(PathBuf::from(PROJECT_CONFIG_FILE))];
+    read_layers(paths.iter().flatten())
+}
+
+/// Read the config files that exist among `paths`, later files taking precedence
+fn read_layers<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Result<Config> {
+    let mut config = Config::default();
+    for path in paths {
+        if path.is_file() {
+            config.extend(read_config(path)?);
+        }
+    }
+
+    Ok(config)
+}
+
+/// Location of the user-level config file
+fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("fweh").join("config.toml"))
+}
+
+/// Read and parse a single config file
+fn read_config(path: &Path) -> Result<Config> {
+    debug!("Loading config file: {}", path.display());
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Args;
+    use clap::Parser;
+
+    /// Write `contents` to a file unique to this test run and return its path
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("fweh-config-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Layer command line `flags` over `preset` the way the binary does
+    fn merge(preset: Preset, flags: &[&str]) -> Preset {
+        let args = Args::try_parse_from(["fweh", "in.png"].iter().chain(flags)).unwrap();
+        args.overrides().or(preset)
+    }
+
+    fn parse(contents: &str) -> Config {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn project_config_replaces_user_presets_by_name() {
+        let user = write_config(
+            "layers-user",
+            r#"
+            [presets.docs]
+            scale = 120
+            roundness = 4
+
+            [presets.slides]
+            ratio = "16:9"
+            "#,
+        );
+        let project = write_config(
+            "layers-project",
+            r#"
+            [presets.docs]
+            roundness = 8
+            "#,
+        );
+
+        let config = read_layers([&user, &project]).unwrap();
+        let docs = config.preset("docs").unwrap();
+        assert_eq!(docs.roundness, Some(8.0));
+        assert_eq!(docs.scale, None, "presets are replaced whole, not merged");
+        assert_eq!(
+            config.preset("slides").unwrap().ratio.as_deref(),
+            Some("16:9")
+        );
+
+        // The order of the files decides which one wins
+        let config = read_layers([&project, &user]).unwrap();
+        assert_eq!(config.preset("docs").unwrap().roundness, Some(4.0));
+
+        std::fs::remove_file(user).unwrap();
+        std::fs::remove_file(project).unwrap();
+    }
+
+    #[test]
+    fn missing_config_files_are_skipped() {
+        let missing = std::env::temp_dir().join("fweh-config-does-not-exist.toml");
+        let config = read_layers([&missing]).unwrap();
+        assert!(config.presets.is_empty());
+    }
+
+    #[test]
+    fn malformed_config_names_the_file() {
+        let path = write_config("malformed", "[presets.docs\nscale = ");
+        let err = read_layers([&path]).unwrap_err();
+        assert!(format!("{}", err).contains("Failed to parse config file"));
+        assert!(format!("{}", err).contains(&*path.to_string_lossy()));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_presets_list_the_available_ones() {
+        let config = parse("[presets.b]\n[presets.a]\n");
+        let err = config.preset("c").unwrap_err();
+        assert_eq!(format!("{}", err), "Unknown preset 'c' (available: a, b)");
+
+        let err = Config::default().preset("c").unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Unknown preset 'c': no presets are configured"
+        );
+    }
+
+    #[test]
+    fn flags_override_preset_fields_one_by_one() {
+        let preset = parse(
+            r##"
+            [presets.docs]
+            scale = 120
+            roundness = 4
+            offset = "10,0"
+            background = ["colr:white", "patt:dots"]
+            noise = "gaussian"
+            ratio = "16:9"
+            shadows = "0 2 4 black"
+            elevation = 2
+            "##,
+        )
+        .preset("docs")
+        .unwrap();
+
+        // No flags: everything comes from the preset
+        let merged = merge(preset.clone(), &[]);
+        assert_eq!(merged.scale, Some(120.0));
+        assert_eq!(merged.roundness, Some(4.0));
+        assert_eq!(merged.offset.as_deref(), Some("10,0"));
+        assert_eq!(
+            merged.background,
+            Some(vec!["colr:white".to_string(), "patt:dots".to_string()])
+        );
+        assert_eq!(merged.noise.as_deref(), Some("gaussian"));
+        assert_eq!(merged.ratio.as_deref(), Some("16:9"));
+        assert_eq!(merged.shadows, Some(vec!["0 2 4 black".to_string()]));
+        assert_eq!(merged.elevation.as_deref(), Some("2"));
+
+        // Each flag replaces only its own field
+        let merged = merge(
+            preset,
+            &[
+                "--roundness",
+                "8",
+                "--background",
+                "colr:black",
+                "--elevation",
+                "modal",
+            ],
+        );
+        assert_eq!(merged.scale, Some(120.0));
+        assert_eq!(merged.roundness, Some(8.0));
+        assert_eq!(merged.offset.as_deref(), Some("10,0"));
+        assert_eq!(merged.background, Some(vec!["colr:black".to_string()]));
+        assert_eq!(merged.noise.as_deref(), Some("gaussian"));
+        assert_eq!(merged.ratio.as_deref(), Some("16:9"));
+        assert_eq!(merged.shadows, Some(vec!["0 2 4 black".to_string()]));
+        assert_eq!(merged.elevation.as_deref(), Some("modal"));
+    }
+
+    #[test]
+    fn flags_override_shadow_section_fields_one_by_one() {
+        let preset = parse(
+            r#"
+            [presets.docs.shadow]
+            offset = "0,12"
+            color = "navy"
+            radius = 30
+            opacity = 0.5
+            blend = "multiply"
+            "#,
+        )
+        .preset("docs")
+        .unwrap();
+
+        let shadow = merge(preset.clone(), &["--shadow-radius", "5"])
+            .shadow
+            .unwrap();
+        assert_eq!(shadow.enabled, Some(true), "the section enables the shadow");
+        assert_eq!(shadow.offset.as_deref(), Some("0,12"));
+        assert_eq!(shadow.color.as_deref(), Some("navy"));
+        assert_eq!(shadow.radius, Some(5.0));
+        assert_eq!(shadow.opacity, Some(0.5));
+        assert_eq!(shadow.spread, None);
+        assert_eq!(shadow.blend.as_deref(), Some("multiply"));
+
+        let shadow = merge(preset, &["--shadow-offset", "4,4", "--shadow-spread", "-2"])
+            .shadow
+            .unwrap();
+        assert_eq!(shadow.offset.as_deref(), Some("4,4"));
+        assert_eq!(shadow.color.as_deref(), Some("navy"));
+        assert_eq!(shadow.radius, Some(30.0));
+        assert_eq!(shadow.spread, Some(-2.0));
+    }
+
+    #[test]
+    fn shadow_tweaks_alone_do_not_enable_a_shadow() {
+        let shadow = merge(Preset::default(), &["--shadow-radius", "5"])
+            .shadow
+            .unwrap();
+        assert_eq!(shadow.enabled, None);
+        assert_eq!(shadow.radius, Some(5.0));
+
+        let shadow = merge(Preset::default(), &["--shadow-offset", "4,4"])
+            .shadow
+            .unwrap();
+        assert_eq!(shadow.enabled, Some(true));
+    }
+
+    #[test]
+    fn disabled_shadow_section_keeps_its_values() {
+        let preset = parse(
+            r#"
+            [presets.docs.shadow]
+            enabled = false
+            radius = 30
+            "#,
+        )
+        .preset("docs")
+        .unwrap();
+
+        let shadow = merge(preset.clone(), &[]).shadow.unwrap();
+        assert_eq!(shadow.enabled, Some(false));
+        assert_eq!(shadow.radius, Some(30.0));
+
+        // An explicit offset turns it back on
+        let shadow = merge(preset, &["--shadow-offset", "4,4"]).shadow.unwrap();
+        assert_eq!(shadow.enabled, Some(true));
+        assert_eq!(shadow.radius, Some(30.0));
+    }
+
+    #[test]
+    fn no_shadow_turns_off_every_preset_shadow() {
+        let preset = parse(
+            r#"
+            [presets.docs]
+            shadows = ["0 2 4 black", "0 8 16 black"]
+            elevation = "floating"
+
+            [presets.docs.shadow]
+            offset = "0,12"
+            "#,
+        )
+        .preset("docs")
+        .unwrap();
+
+        let merged = merge(preset, &["--no-shadow"]);
+        assert_eq!(merged.shadow.unwrap().enabled, Some(false));
+        assert_eq!(merged.shadows, Some(Vec::new()));
+        assert_eq!(merged.elevation.as_deref(), Some("0"));
+    }
+}
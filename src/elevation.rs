@@ -0,0 +1,91 @@
+//! Elevation presets
+//!
+//! Elevation levels from 0 (flat) to 5 expand into a stack of two shadows, as
+//! in Material Design 3: a tight, darker key shadow for contact and a wider,
+//! fainter ambient one. The tokens are defined for interface elements a few
+//! hundred pixels across, so they are scaled with the image's shorter side;
+//! screenshots of any resolution then get the same shadow relative to their
+//! size. `subtle`, `floating` and `modal` name levels 1, 3 and 5.
+
+use anyhow::{anyhow, Result};
+
+use crate::shadow::ShadowOptions;
+use crate::utils::Point;
+
+/// Shorter image side, in pixels, at which the tokens are used unscaled
+const REFERENCE_SIZE: f32 = 250.0;
+
+/// Key and ambient shadows for levels 1 to 5 as (y offset, CSS blur, spread, opacity)
+const LEVELS: [[(f32, f32, f32, f32); 2]; 5] = [
+    [(1.0, 2.0, 0.0, 0.3), (1.0, 3.0, 1.0, 0.15)],
+    [(1.0, 2.0, 0.0, 0.3), (2.0, 6.0, 2.0, 0.15)],
+    [(1.0, 3.0, 0.0, 0.3), (4.0, 8.0, 3.0, 0.15)],
+    [(2.0, 3.0, 0.0, 0.3), (6.0, 10.0, 4.0, 0.15)],
+    [(4.0, 4.0, 0.0, 0.3), (8.0, 12.0, 6.0, 0.15)],
+];
+
+/// An elevation level that expands into a shadow stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Elevation(u8);
+
+impl Elevation {
+    /// The highest level
+    pub const MAX: u8 = LEVELS.len() as u8;
+
+    /// Create an elevation from a level between 0 and [`Elevation::MAX`]
+    pub fn new(level: u8) -> Result<Self> {
+        if level > Self::MAX {
+            return Err(anyhow!(
+                "Elevation must be between 0 and {}: {}",
+                Self::MAX,
+                level
+            ));
+        }
+        Ok(Self(level))
+    }
+
+    /// Parse a level (`0`-`5`) or a name (`flat`, `subtle`, `floating`, `modal`)
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.trim().to_ascii_lowercase().as_str() {
+            "flat" | "none" => Ok(Self(0)),
+            "subtle" => Ok(Self(1)),
+            "floating" => Ok(Self(3)),
+            "modal" => Ok(Self(5)),
+            other => other
+                .parse::<u8>()
+                .map_err(|_| {
+                    anyhow!(
+                        "Unknown elevation '{}' (expected 0-{}, subtle, floating or modal)",
+                        other,
+                        Self::MAX
+                    )
+                })
+                .and_then(Self::new),
+        }
+    }
+
+    /// The level, from 0 to [`Elevation::MAX`]
+    pub fn level(self) -> u8 {
+        self.0
+    }
+
+    /// The shadows for an image of `width` x `height`, from top to bottom
+    pub fn shadows(self, width: u32, height: u32) -> Vec<ShadowOptions> {
+        let Some(layers) = self.0.checked_sub(1).map(|index| LEVELS[index as usize]) else {
+            return Vec::new();
+        };
+
+        let scale = width.min(height) as f32 / REFERENCE_SIZE;
+        layers
+            .iter()
+            .map(|&(y, blur, spread, opacity)| ShadowOptions {
+                offset: Point::new(0.0, y * scale),
+                color: "black".to_string(),
+                radius: blur * scale / 2.0,
+                opacity,
+                spread: spread * scale,
+                ..ShadowOptions::default()
+            })
+            .collect()
+    }
+}
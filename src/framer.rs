@@ -0,0 +1,48 @@
+//! High-level framing API
+
+use anyhow::Result;
+use image::{DynamicImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+use crate::image_processing::{frame_image, frame_rgba, load_image, save_image, ProcessingOptions};
+
+/// Frames images according to a fixed set of processing options
+#[derive(Debug, Clone, Default)]
+pub struct Framer {
+    options: ProcessingOptions,
+}
+
+impl Framer {
+    /// Create a new framer with the given options
+    pub fn new(options: ProcessingOptions) -> Self {
+        Self { options }
+    }
+
+    /// The options this framer applies
+    pub fn options(&self) -> &ProcessingOptions {
+        &self.options
+    }
+
+    /// Frame an in-memory image of any pixel format
+    pub fn frame(&self, image: &DynamicImage) -> Result<RgbaImage> {
+        frame_image(image, &self.options)
+    }
+
+    /// Frame an in-memory RGBA image
+    pub fn frame_rgba(&self, image: &RgbaImage) -> Result<RgbaImage> {
+        frame_rgba(image.clone(), &self.options)
+    }
+
+    /// Frame an image file and save the result to `output_path`
+    pub fn frame_file(&self, input_path: &Path, output_path: &Path) -> Result<PathBuf> {
+        let input = load_image(input_path)?;
+        let framed = self.frame(&input)?;
+        save_image(&framed, output_path)
+    }
+}
+
+impl From<ProcessingOptions> for Framer {
+    fn from(options: ProcessingOptions) -> Self {
+        Self::new(options)
+    }
+}
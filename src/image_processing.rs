@@ -1,16 +1,21 @@
 //! Core image processing functions
 
 use anyhow::Result;
-use image::{imageops, RgbaImage};
-use log::debug;
-use rgb;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use log::{debug, warn};
 use std::path::{Path, PathBuf};
 
-use crate::background::{create_background, BackgroundType};
+use crate::background::{create_layered_background, BackgroundLayer, BackgroundType};
+use crate::compositor;
+use crate::elevation::Elevation;
 use crate::error::FwehError;
-use crate::shadow::{add_drop_shadow, ShadowOptions};
+use crate::noise::{apply_noise, NoiseOptions};
+use crate::shadow::{create_shadow, ShadowOptions};
 use crate::utils::{calculate_aspect_ratio, calculate_padding, CornerRadii, Point};
 
+/// Largest output canvas, in pixels
+const MAX_CANVAS_PIXELS: u64 = 1 << 28;
+
 /// Options for aspect ratio
 #[derive(Debug, Clone, Copy)]
 pub struct AspectRatio {
@@ -37,29 +42,134 @@ pub struct ProcessingOptions {
     /// Offset of the image from center
     pub offset: Point,
 
-    /// Shadow options (None for no shadow)
-    pub shadow: Option<ShadowOptions>,
+    /// Drop shadows, from top to bottom (empty for no shadow)
+    pub shadows: Vec<ShadowOptions>,
+
+    /// Elevation whose shadow stack goes beneath `shadows` (None for no elevation)
+    pub elevation: Option<Elevation>,
+
+    /// Background layers, from bottom to top
+    pub backgrounds: Vec<BackgroundLayer>,
 
-    /// Background type
-    pub background: BackgroundType,
+    /// Grain added on top of the background (None for a clean background)
+    pub noise: Option<NoiseOptions>,
 
     /// Target aspect ratio (None to maintain original)
     pub ratio: Option<AspectRatio>,
 }
 
-/// Process an image with the given options
+impl ProcessingOptions {
+    /// Check that the options describe a frame that can be rendered
+    pub fn validate(&self) -> Result<(), FwehError> {
+        if !self.scale.is_finite() || self.scale <= 0.0 {
+            return Err(FwehError::InvalidParameter(format!(
+                "Scale must be a positive number: {}",
+                self.scale
+            )));
+        }
+        if !(0.0..=100.0).contains(&self.roundness) {
+            return Err(FwehError::InvalidParameter(format!(
+                "Roundness must be between 0 and 100: {}",
+                self.roundness
+            )));
+        }
+        if let Some(ratio) = self.ratio {
+            if ratio.width == 0 || ratio.height == 0 {
+                return Err(FwehError::InvalidParameter(format!(
+                    "Aspect ratio sides must be positive: {}:{}",
+                    ratio.width, ratio.height
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        Self {
+            scale: 110.0,
+            roundness: 0.0,
+            offset: Point::new(0.0, 0.0),
+            shadows: Vec::new(),
+            elevation: None,
+            backgrounds: vec![BackgroundType::Color("black".to_string()).into()],
+            noise: None,
+            ratio: None,
+        }
+    }
+}
+
+/// Process an image file with the given options and save the result
+///
+/// This is a thin layer over [`load_image`], [`frame_image`] and [`save_image`].
 pub fn process_image(
     input_path: &Path,
     output_path: &Path,
     options: ProcessingOptions,
 ) -> Result<PathBuf> {
-    // Load the input image
-    let input_image = image::open(input_path).map_err(|e| FwehError::ImageLoadError(e))?;
+    let input_image = load_image(input_path)?;
+    let framed = frame_image(&input_image, &options)?;
+    save_image(&framed, output_path)
+}
+
+/// Load an image from disk
+pub fn load_image(input_path: &Path) -> Result<DynamicImage> {
+    if !input_path.is_file() {
+        return Err(FwehError::InputFileNotFound(input_path.to_path_buf()).into());
+    }
 
-    let input_rgba = input_image.to_rgba8();
+    let image = image::open(input_path).map_err(FwehError::ImageLoadError)?;
+    debug!("Loaded input image: {}", input_path.display());
+
+    Ok(image)
+}
+
+/// Save a framed image to disk, inferring the format from the file extension
+pub fn save_image(image: &RgbaImage, output_path: &Path) -> Result<PathBuf> {
+    if let Some(parent) = output_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.is_dir() {
+            return Err(FwehError::OutputDirectoryNotFound(parent.to_path_buf()).into());
+        }
+    }
+
+    // JPEG has no alpha channel, so transparent areas would turn black
+    if ImageFormat::from_path(output_path).ok() == Some(ImageFormat::Jpeg)
+        && image.pixels().any(|pixel| pixel[3] < 255)
+    {
+        warn!(
+            "{} cannot store transparency; use PNG or WebP for transparent output",
+            output_path.display()
+        );
+    }
+
+    image
+        .save(output_path)
+        .map_err(|e| FwehError::ImageSaveError(e.to_string()))?;
+    debug!("Saved output image: {}", output_path.display());
+
+    Ok(output_path.to_path_buf())
+}
+
+/// Frame an in-memory image, returning the composited result
+pub fn frame_image(image: &DynamicImage, options: &ProcessingOptions) -> Result<RgbaImage> {
+    frame_rgba(image.to_rgba8(), options)
+}
+
+/// Frame an owned RGBA image, returning the composited result
+pub(crate) fn frame_rgba(input_rgba: RgbaImage, options: &ProcessingOptions) -> Result<RgbaImage> {
     let (width, height) = input_rgba.dimensions();
+    if width == 0 || height == 0 {
+        return Err(FwehError::InvalidParameter(format!(
+            "Cannot frame an empty image ({}x{})",
+            width, height
+        ))
+        .into());
+    }
+
+    options.validate()?;
 
-    debug!("Loaded input image: {}x{}", width, height);
+    debug!("Framing image: {}x{}", width, height);
 
     // Calculate target aspect ratio
     let target_ratio = options.ratio.map(|r| r.as_f32()).unwrap_or_else(|| {
@@ -69,6 +179,32 @@ pub fn process_image(
 
     debug!("Target aspect ratio: {}", target_ratio);
 
+    // Calculate dimensions for the background
+    let (new_width, new_height, _, _, _, _) =
+        calculate_padding(width, height, target_ratio, options.scale);
+    if new_width == 0 || new_height == 0 || new_width as u64 * new_height as u64 > MAX_CANVAS_PIXELS
+    {
+        return Err(FwehError::InvalidParameter(format!(
+            "Scale {}% and aspect ratio {} give an unusable {}x{} canvas",
+            options.scale, target_ratio, new_width, new_height
+        ))
+        .into());
+    }
+
+    debug!("Creating background of size {}x{}", new_width, new_height);
+
+    // Create background; ambient backgrounds sample the input before rounding
+    let mut background = create_layered_background(
+        new_width,
+        new_height,
+        &options.backgrounds,
+        Some(&input_rgba),
+    )?;
+
+    if let Some(noise) = &options.noise {
+        apply_noise(&mut background, noise);
+    }
+
     // Apply corner rounding if needed
     let mut processed = input_rgba;
 
@@ -77,41 +213,34 @@ pub fn process_image(
         processed = round_corners(&processed, options.roundness)?;
     }
 
-    // Apply drop shadow if needed
-    let mut with_shadow = processed.clone();
-    if let Some(shadow_options) = &options.shadow {
-        debug!("Adding drop shadow");
-        with_shadow = add_drop_shadow(&processed, shadow_options)?;
-    }
-
-    // Calculate dimensions for the background
-    let (new_width, new_height, _, _, _, _) =
-        calculate_padding(width, height, target_ratio, options.scale);
-
-    debug!("Creating background of size {}x{}", new_width, new_height);
-
-    // Create background
-    let mut background = create_background(new_width, new_height, &options.background)?;
-
     // Calculate position to place the image on the background
     let x = (new_width as f32 - width as f32) / 2.0 + options.offset.x;
     let y = (new_height as f32 - height as f32) / 2.0 + options.offset.y;
+    let (x, y) = (x.round() as i64, y.round() as i64);
+
+    // Blend the drop shadows into the background, bottom first, then draw the
+    // image over them; the image stays centred however far the shadows reach
+    let elevation = options
+        .elevation
+        .map(|elevation| elevation.shadows(width, height))
+        .unwrap_or_default();
+    for shadow_options in options.shadows.iter().chain(&elevation).rev() {
+        debug!("Adding drop shadow");
+        let (shadow, (shadow_x, shadow_y)) = create_shadow(&processed, shadow_options)?;
+        compositor::composite(
+            &mut background,
+            &shadow,
+            x.saturating_add(shadow_x),
+            y.saturating_add(shadow_y),
+            shadow_options.blend,
+            1.0,
+        );
+    }
 
     debug!("Placing image at position ({}, {})", x, y);
+    compositor::over(&mut background, &processed, x, y);
 
-    // Composite the processed image onto the background
-    if options.shadow.is_some() {
-        imageops::overlay(&mut background, &with_shadow, x as i64, y as i64)
-    } else {
-        imageops::overlay(&mut background, &processed, x as i64, y as i64);
-    };
-
-    // Save the final image
-    background
-        .save(output_path)
-        .map_err(|e| FwehError::ImageSaveError(e.to_string()))?;
-
-    Ok(output_path.to_path_buf())
+    Ok(background)
 }
 
 /// Round the corners of an image
@@ -141,10 +270,12 @@ fn round_corners(image: &RgbaImage, radius_percentage: f32) -> Result<RgbaImage>
 /// Round the corners of an image buffer (implementation from the provided code)
 fn round(img: &mut RgbaImage, radius: &mut CornerRadii) {
     let (width, height) = img.dimensions();
-    assert!(radius.0 + radius.1 <= width);
-    assert!(radius.3 + radius.2 <= width);
-    assert!(radius.0 + radius.3 <= height);
-    assert!(radius.1 + radius.2 <= height);
+
+    // Neighbouring corners must not overlap, so no radius exceeds half a side
+    let limit = width.min(height) / 2;
+    for r in [&mut radius.0, &mut radius.1, &mut radius.2, &mut radius.3] {
+        *r = (*r).min(limit);
+    }
 
     // top left
     border_radius(img, radius.0, |x, y| (x - 1, y - 1));
@@ -216,6 +347,8 @@ fn border_radius(img: &mut RgbaImage, r: u32, coordinates: impl Fn(u32, u32) ->
                 p += (2 * x + 2) as i32;
             } else {
                 // draw when moving to next pixel in y-direction
+                // `is_multiple_of` needs Rust 1.87
+                #[allow(clippy::manual_is_multiple_of)]
                 if y % 16 == 0 {
                     draw(img, alpha, x / 16, y / 16);
                     draw(img, alpha, y / 16, x / 16);
@@ -258,3 +391,130 @@ pub fn to_image_rgba(px: RgbRgba<u8>) -> ImageRgba<u8> {
     // ImageRgba is a tuple‐struct around [u8;4]
     ImageRgba([px.r, px.g, px.b, px.a])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    fn frame(width: u32, height: u32, options: &ProcessingOptions) -> Result<RgbaImage> {
+        frame_rgba(RgbaImage::from_pixel(width, height, RED), options)
+    }
+
+    #[test]
+    fn pads_by_scale_and_keeps_ratio() {
+        let options = ProcessingOptions {
+            scale: 120.0,
+            ..ProcessingOptions::default()
+        };
+        let framed = frame(100, 50, &options).unwrap();
+        assert_eq!(framed.dimensions(), (120, 60));
+    }
+
+    #[test]
+    fn pads_to_target_ratio() {
+        let options = ProcessingOptions {
+            scale: 120.0,
+            ratio: Some(AspectRatio {
+                width: 1,
+                height: 1,
+            }),
+            ..ProcessingOptions::default()
+        };
+        let framed = frame(100, 50, &options).unwrap();
+        assert_eq!(framed.dimensions(), (120, 120));
+
+        // Centred vertically in the square
+        assert_eq!(*framed.get_pixel(10, 34), BLACK);
+        assert_eq!(*framed.get_pixel(10, 35), RED);
+        assert_eq!(*framed.get_pixel(109, 84), RED);
+        assert_eq!(*framed.get_pixel(109, 85), BLACK);
+    }
+
+    #[test]
+    fn centres_image_on_background() {
+        let options = ProcessingOptions {
+            scale: 120.0,
+            ..ProcessingOptions::default()
+        };
+        let framed = frame(100, 50, &options).unwrap();
+
+        assert_eq!(*framed.get_pixel(9, 5), BLACK);
+        assert_eq!(*framed.get_pixel(10, 4), BLACK);
+        assert_eq!(*framed.get_pixel(10, 5), RED);
+        assert_eq!(*framed.get_pixel(109, 54), RED);
+        assert_eq!(*framed.get_pixel(110, 54), BLACK);
+        assert_eq!(*framed.get_pixel(109, 55), BLACK);
+    }
+
+    #[test]
+    fn applies_offset() {
+        let options = ProcessingOptions {
+            scale: 120.0,
+            offset: Point::new(-4.0, 3.0),
+            ..ProcessingOptions::default()
+        };
+        let framed = frame(100, 50, &options).unwrap();
+
+        assert_eq!(*framed.get_pixel(5, 8), BLACK);
+        assert_eq!(*framed.get_pixel(6, 8), RED);
+        assert_eq!(*framed.get_pixel(6, 7), BLACK);
+    }
+
+    #[test]
+    fn clamps_large_roundness() {
+        for roundness in [60.0, 100.0] {
+            let options = ProcessingOptions {
+                roundness,
+                ..ProcessingOptions::default()
+            };
+            let framed = frame(40, 30, &options).unwrap();
+            assert_eq!(framed.dimensions(), (44, 33));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let invalid = [
+            ProcessingOptions {
+                scale: 0.0,
+                ..ProcessingOptions::default()
+            },
+            ProcessingOptions {
+                scale: f32::NAN,
+                ..ProcessingOptions::default()
+            },
+            ProcessingOptions {
+                roundness: 101.0,
+                ..ProcessingOptions::default()
+            },
+            ProcessingOptions {
+                ratio: Some(AspectRatio {
+                    width: 0,
+                    height: 1,
+                }),
+                ..ProcessingOptions::default()
+            },
+            ProcessingOptions {
+                ratio: Some(AspectRatio {
+                    width: 1_000_000,
+                    height: 1,
+                }),
+                ..ProcessingOptions::default()
+            },
+        ];
+        for options in invalid {
+            assert!(frame(40, 30, &options).is_err(), "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn rejects_empty_image() {
+        let options = ProcessingOptions::default();
+        assert!(frame(0, 0, &options).is_err());
+        assert!(frame(10, 0, &options).is_err());
+    }
+}
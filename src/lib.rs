@@ -0,0 +1,64 @@
+//! Fweh - A library for framing images with shadows, rounded corners, and backgrounds
+//!
+//! The [`Framer`] type is the main entry point: build it from a set of
+//! [`ProcessingOptions`] and use it to frame in-memory images or image files.
+//!
+//! ```no_run
+//! use fweh::{BackgroundType, Framer, ProcessingOptions};
+//!
+//! let options = ProcessingOptions {
+//!     roundness: 5.0,
+//!     backgrounds: vec![BackgroundType::Color("#1e1e2e".to_string()).into()],
+//!     ..ProcessingOptions::default()
+//! };
+//!
+//! let input = image::open("screenshot.png")?;
+//! let framed = Framer::new(options).frame(&input)?;
+//! framed.save("framed.png")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub(crate) mod ambient;
+pub(crate) mod background;
+pub(crate) mod batch;
+pub(crate) mod blend;
+pub(crate) mod blur;
+pub(crate) mod color;
+pub(crate) mod compositor;
+pub(crate) mod elevation;
+pub(crate) mod error;
+pub(crate) mod framer;
+pub(crate) mod gradient;
+pub(crate) mod image_processing;
+pub(crate) mod mesh;
+pub(crate) mod noise;
+pub(crate) mod palette;
+pub(crate) mod pattern;
+pub(crate) mod shadow;
+pub(crate) mod utils;
+
+pub use ambient::AmbientOptions;
+pub use background::{
+    create_background, create_layered_background, BackgroundLayer, BackgroundType,
+};
+pub use batch::{
+    expand_inputs, is_batch, plan_batch, BatchJob, BatchReport, DEFAULT_NAME_TEMPLATE,
+};
+pub use blend::BlendMode;
+pub use blur::gaussian_blur;
+pub use color::parse_color;
+pub use elevation::Elevation;
+pub use error::FwehError;
+pub use framer::Framer;
+pub use gradient::{
+    parse_conic_gradient, parse_gradient, parse_radial_gradient, ConicGradient, GradientStop,
+    HueInterpolation, InterpolationSpace, Length, LinearDirection, LinearGradient, Position,
+    RadialExtent, RadialGradient, RadialShape,
+};
+pub use image_processing::{
+    frame_image, load_image, process_image, save_image, AspectRatio, ProcessingOptions,
+};
+pub use noise::{NoiseKind, NoiseOptions};
+pub use palette::{extract_palette, PaletteColor, PALETTE_SIZE};
+pub use shadow::{add_drop_shadow, create_shadow, parse_shadows, ShadowOptions};
+pub use utils::Point;
@@ -1,19 +1,22 @@
 //! Fweh - A tool for framing images with shadows, rounded corners, and backgrounds
 //!
 //! This application allows you to process images by adding backgrounds, shadows, and
-//! rounded corners, as well as resizing to specific aspect ratios.
+//! rounded corners, as well as resizing to specific aspect ratios. The heavy lifting
+//! lives in the `fweh` library; this binary only translates command line arguments
+//! into [`fweh::ProcessingOptions`].
 
 mod args;
-mod background;
-mod error;
-mod image_processing;
-mod shadow;
-mod utils;
-
-use anyhow::Result;
-use args::parse_args;
-use image_processing::process_image;
+mod config;
+
+use anyhow::{anyhow, Result};
+use args::{parse_args, resolve_options};
+use config::{load_config, Preset};
+use fweh::{
+    expand_inputs, extract_palette, is_batch, load_image, plan_batch, BatchJob, Framer,
+    PALETTE_SIZE,
+};
 use log::{error, info};
+use std::path::PathBuf;
 
 fn main() -> Result<()> {
     // Initialize logger
@@ -24,22 +27,102 @@ fn main() -> Result<()> {
         Ok(args) => args,
         Err(e) => {
             error!("Failed to parse arguments: {}", e);
-            return Err(e.into());
+            return Err(e);
         }
     };
 
-    info!("Processing image: {}", args.input.display());
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
+
+    // Work out which files to frame and where each result goes
+    let inputs = expand_inputs(&args.inputs, &args.name)?;
+    let batch = is_batch(&args.inputs) || args.out_dir.is_some();
 
-    // Process the image
-    match process_image(&args.input.clone(), &args.output.clone(), args.into()) {
-        Ok(output_path) => {
-            info!("Successfully processed image: {}", output_path.display());
-            println!("{}", output_path.display());
-            Ok(())
+    // Printing the palette is a query; nothing is framed
+    if args.print_palette {
+        for input in &inputs {
+            if inputs.len() > 1 {
+                println!("{}:", input.display());
+            }
+            let image = load_image(input)?.to_rgba8();
+            for color in extract_palette(&image, PALETTE_SIZE) {
+                println!("{}  {:5.1}%", color.hex(), color.weight * 100.0);
+            }
         }
-        Err(e) => {
-            error!("Failed to process image: {}", e);
-            Err(e)
+        return Ok(());
+    }
+
+    let jobs = if !batch {
+        vec![BatchJob {
+            input: inputs[0].clone(),
+            output: args
+                .output
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("output.png")),
+        }]
+    } else if args.output.is_some() {
+        return Err(anyhow!(
+            "--output only applies to a single input; use --out-dir and --name for batches"
+        ));
+    } else {
+        if let Some(out_dir) = &args.out_dir {
+            std::fs::create_dir_all(out_dir)?;
+        }
+        plan_batch(&inputs, args.out_dir.as_deref(), &args.name)?
+    };
+
+    // Layer the command line flags over the selected preset; config files are
+    // only read when a preset is asked for, so a broken fweh.toml can't fail plain runs
+    let preset = match &args.preset {
+        Some(name) => load_config(args.config.as_deref())?.preset(name)?,
+        None => Preset::default(),
+    };
+    let framer = Framer::new(resolve_options(args.overrides().or(preset))?);
+
+    // Single images keep the simple, fail-fast behaviour
+    if !batch {
+        let job = &jobs[0];
+        info!("Processing image: {}", job.input.display());
+
+        return match framer.frame_file(&job.input, &job.output) {
+            Ok(output_path) => {
+                info!("Successfully processed image: {}", output_path.display());
+                println!("{}", output_path.display());
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to process image: {}", e);
+                Err(e)
+            }
+        };
+    }
+
+    // Process the batch, reporting every failure before giving up
+    let report = framer.frame_batch(&jobs);
+    for output_path in &report.succeeded {
+        println!("{}", output_path.display());
+    }
+
+    info!(
+        "Framed {} of {} images ({} failed)",
+        report.succeeded.len(),
+        report.total(),
+        report.failed.len()
+    );
+
+    if report.is_success() {
+        Ok(())
+    } else {
+        for (input, e) in &report.failed {
+            error!("  {}: {}", input.display(), e);
         }
+        Err(anyhow!(
+            "{} of {} images failed",
+            report.failed.len(),
+            report.total()
+        ))
     }
 }
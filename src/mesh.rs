@@ -0,0 +1,330 @@
+//! Mesh ("aurora") gradients
+//!
+//! A mesh gradient is a set of colored control points blended smoothly across
+//! the canvas, either with Gaussian splats (soft blobs of color, the default)
+//! or inverse distance weighting (every point pulls on every pixel). Colors
+//! are mixed in Oklab like the other gradients.
+//!
+//! Points are comma-separated `<color> [<x> <y>]` entries, with positions
+//! written like gradient centres (`30% 40%`, `top left`, `120px 80px`). Colors
+//! without a position are scattered randomly, and without any colors `points`
+//! random hues are generated; both follow `seed`. Options are `key=value`
+//! entries: `method`, `spread`, `power`, `points` and `seed`.
+
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
+use log::debug;
+use rgb::RGBA8;
+
+use crate::color::{oklab_to_srgb, parse_color, to_rgba8};
+use crate::gradient::{parse_position, render, InterpolationSpace, Length, Position};
+use crate::noise::{splitmix64, unit};
+use crate::utils::{parse_option_number, parse_options, split_top_level};
+
+/// How control points are blended
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MeshMethod {
+    /// Gaussian splats with a standard deviation of `spread` times the canvas diagonal
+    #[default]
+    Gaussian,
+
+    /// Inverse distance weighting with weights of 1 / distance^`power`
+    InverseDistance,
+}
+
+/// A colored control point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshPoint {
+    pub color: RGBA8,
+    pub position: Position,
+}
+
+/// A parsed mesh gradient
+#[derive(Debug, Clone)]
+pub struct MeshGradient {
+    pub points: Vec<MeshPoint>,
+    pub method: MeshMethod,
+
+    /// Splat size as a fraction of the canvas diagonal (Gaussian only)
+    pub spread: f32,
+
+    /// Distance exponent (inverse distance only)
+    pub power: f32,
+}
+
+/// Parse a mesh gradient specification
+pub fn parse_mesh(spec: &str) -> Result<MeshGradient> {
+    let mut mesh = MeshGradient {
+        points: Vec::new(),
+        method: MeshMethod::default(),
+        spread: 0.3,
+        power: 2.0,
+    };
+    let mut seed = 0u64;
+    let mut random_points = 4usize;
+    let mut unplaced = Vec::new();
+
+    for (key, value) in parse_options(spec) {
+        let number = || match parse_option_number(&format!("mesh {}", key), value)? {
+            number if number > 0.0 => Ok(number),
+            _ => Err(anyhow!("Mesh {} must be positive", key)),
+        };
+
+        match key.to_ascii_lowercase().as_str() {
+            "" => {
+                let tokens = split_top_level(value, ' ')
+                    .into_iter()
+                    .filter(|token| !token.is_empty())
+                    .collect::<Vec<_>>();
+                let color = parse_color(tokens[0])?;
+
+                if tokens.len() == 1 {
+                    unplaced.push(color);
+                } else {
+                    let position = parse_position(&tokens[1..])
+                        .with_context(|| format!("Invalid mesh point '{}'", value))?;
+                    mesh.points.push(MeshPoint { color, position });
+                }
+            }
+            "method" => {
+                mesh.method = match value.to_ascii_lowercase().as_str() {
+                    "gaussian" | "splat" => MeshMethod::Gaussian,
+                    "idw" | "inverse-distance" => MeshMethod::InverseDistance,
+                    other => {
+                        return Err(anyhow!(
+                            "Unknown mesh method '{}' (expected gaussian or idw)",
+                            other
+                        ))
+                    }
+                }
+            }
+            "spread" => mesh.spread = number()?,
+            "power" => mesh.power = number()?,
+            "points" => {
+                random_points = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid mesh points '{}'", value))?
+            }
+            "seed" => {
+                seed = value
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid mesh seed '{}'", value))?
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unknown mesh option '{}' (expected method, spread, power, points or seed)",
+                    other
+                ))
+            }
+        }
+    }
+
+    // Without any colors, make up a palette of evenly bright random hues
+    let mut random = (0u64..).map(|i| unit(splitmix64(seed ^ splitmix64(i))));
+    if mesh.points.is_empty() && unplaced.is_empty() {
+        unplaced = (0..random_points)
+            .map(|_| random_color(random.next().unwrap_or_default()))
+            .collect();
+    }
+
+    for color in unplaced {
+        let mut next = || Length::Percent(random.next().unwrap_or_default());
+        mesh.points.push(MeshPoint {
+            color,
+            position: Position {
+                x: next(),
+                y: next(),
+            },
+        });
+    }
+
+    if mesh.points.is_empty() {
+        return Err(anyhow!("Mesh gradient needs at least one point"));
+    }
+
+    Ok(mesh)
+}
+
+/// Create a mesh gradient background
+pub fn create_mesh_gradient(width: u32, height: u32, spec: &str) -> Result<RgbaImage> {
+    let mesh = parse_mesh(spec)?;
+    debug!("Creating mesh gradient: {:?}", mesh);
+
+    let space = InterpolationSpace::Oklab;
+    let diagonal = (width as f32).hypot(height as f32).max(1.0);
+    let points = mesh
+        .points
+        .iter()
+        .map(|point| {
+            (
+                point.position.x.resolve(width as f32) / diagonal,
+                point.position.y.resolve(height as f32) / diagonal,
+                space.to_space(point.color),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let variance = 2.0 * mesh.spread * mesh.spread;
+
+    Ok(render(width, height, |x, y| {
+        let (x, y) = (x / diagonal, y / diagonal);
+
+        // Work with log-weights so far away Gaussian splats do not underflow to zero
+        let log_weight = |&(px, py, _): &(f32, f32, [f32; 4])| {
+            let distance_squared = (x - px).powi(2) + (y - py).powi(2);
+            match mesh.method {
+                MeshMethod::Gaussian => -distance_squared / variance,
+                MeshMethod::InverseDistance => -0.5 * mesh.power * distance_squared.max(1e-12).ln(),
+            }
+        };
+        let max = points
+            .iter()
+            .map(log_weight)
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        let mut mixed = [0.0; 4];
+        let mut total = 0.0;
+        for point in &points {
+            let weight = (log_weight(point) - max).exp();
+            for (channel, value) in mixed.iter_mut().zip(point.2) {
+                *channel += value * weight;
+            }
+            total += weight;
+        }
+
+        space.to_srgb(mixed.map(|channel| channel / total))
+    }))
+}
+
+/// A saturated color of medium lightness with hue `t` (0-1) around the wheel
+fn random_color(t: f32) -> RGBA8 {
+    let hue = t * std::f32::consts::TAU;
+    let (l, chroma) = (0.7, 0.15);
+    let (r, g, b) = oklab_to_srgb(l, chroma * hue.cos(), chroma * hue.sin());
+
+    to_rgba8(r, g, b, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn percent(x: f32, y: f32) -> Position {
+        Position {
+            x: Length::Percent(x),
+            y: Length::Percent(y),
+        }
+    }
+
+    #[test]
+    fn parses_points_and_options() {
+        let mesh =
+            parse_mesh("red 0% 0%, blue right bottom, method=idw, power=3, spread=0.5").unwrap();
+        assert_eq!(
+            mesh.points,
+            [
+                MeshPoint {
+                    color: RGBA8::new(255, 0, 0, 255),
+                    position: percent(0.0, 0.0),
+                },
+                MeshPoint {
+                    color: RGBA8::new(0, 0, 255, 255),
+                    position: percent(1.0, 1.0),
+                },
+            ]
+        );
+        assert_eq!(mesh.method, MeshMethod::InverseDistance);
+        assert_eq!(mesh.power, 3.0);
+        assert_eq!(mesh.spread, 0.5);
+
+        let mesh = parse_mesh("red 0% 0%").unwrap();
+        assert_eq!(mesh.method, MeshMethod::Gaussian);
+        assert_eq!((mesh.spread, mesh.power), (0.3, 2.0));
+    }
+
+    #[test]
+    fn generates_random_points() {
+        let mesh = parse_mesh("").unwrap();
+        assert_eq!(mesh.points.len(), 4);
+
+        let mesh = parse_mesh("points=7,seed=3").unwrap();
+        assert_eq!(mesh.points.len(), 7);
+
+        // Colors without a position are scattered across the canvas
+        let mesh = parse_mesh("red, blue 50% 50%").unwrap();
+        assert_eq!(mesh.points.len(), 2);
+        assert_eq!(mesh.points[1].color, RGBA8::new(255, 0, 0, 255));
+        assert!(matches!(
+            mesh.points[1].position,
+            Position {
+                x: Length::Percent(x),
+                y: Length::Percent(y),
+            } if (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)
+        ));
+    }
+
+    #[test]
+    fn same_seed_gives_same_mesh() {
+        for spec in ["points=5", "red, green, blue", "method=idw"] {
+            let seeded = |seed: u64| {
+                let spec = format!("{},seed={}", spec, seed);
+                (
+                    parse_mesh(&spec).unwrap().points,
+                    create_mesh_gradient(24, 16, &spec).unwrap(),
+                )
+            };
+
+            assert_eq!(seeded(9), seeded(9), "{}", spec);
+            assert_ne!(seeded(9).0, seeded(10).0, "{}", spec);
+            assert_ne!(seeded(9).1, seeded(10).1, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let message = |spec| format!("{}", parse_mesh(spec).unwrap_err());
+
+        assert_eq!(
+            message("spread=-1"),
+            "Invalid mesh spread '-1': expected a non-negative number"
+        );
+        assert_eq!(message("spread=0"), "Mesh spread must be positive");
+        assert_eq!(
+            message("power=NaN"),
+            "Invalid mesh power 'NaN': expected a non-negative number"
+        );
+        assert_eq!(
+            message("method=voronoi"),
+            "Unknown mesh method 'voronoi' (expected gaussian or idw)"
+        );
+        assert_eq!(message("points=many"), "Invalid mesh points 'many'");
+        assert_eq!(message("seed=x"), "Invalid mesh seed 'x'");
+        assert_eq!(
+            message("points=0"),
+            "Mesh gradient needs at least one point"
+        );
+        assert_eq!(
+            message("blur=2"),
+            "Unknown mesh option 'blur' (expected method, spread, power, points or seed)"
+        );
+        assert_eq!(message("red sideways"), "Invalid mesh point 'red sideways'");
+    }
+
+    #[test]
+    fn a_single_point_fills_the_canvas() {
+        let image = create_mesh_gradient(8, 8, "#336699 10% 10%").unwrap();
+        for pixel in image.pixels() {
+            assert_eq!(pixel.0, [0x33, 0x66, 0x99, 255]);
+        }
+    }
+
+    #[test]
+    fn points_keep_their_color_nearby() {
+        let image =
+            create_mesh_gradient(64, 64, "red left top, blue right bottom,spread=0.1").unwrap();
+        let [r, _, b, _] = image.get_pixel(0, 0).0;
+        assert!(r > 240 && b < 15);
+        let [r, _, b, _] = image.get_pixel(63, 63).0;
+        assert!(b > 240 && r < 15);
+    }
+}
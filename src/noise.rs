@@ -0,0 +1,301 @@
+//! Noise and film grain overlays
+//!
+//! Grain is added on top of the finished background to break up banding and
+//! the flat, plasticky look of large gradients. Every sample is derived from a
+//! hash of its position and the seed, so the same seed always produces the
+//! same grain and pixels can be processed in parallel. Options are a
+//! comma-separated list, e.g. `gaussian,amount=0.05,scale=2,chroma,seed=7`.
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use log::debug;
+use rayon::prelude::*;
+
+use crate::utils::{parse_option_number, parse_options};
+
+/// Distribution of the noise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseKind {
+    /// White noise, evenly spread between -amount and +amount
+    Uniform,
+
+    /// White noise with a normal distribution, closest to film grain
+    #[default]
+    Gaussian,
+
+    /// Smooth gradient noise with features about `scale` pixels wide
+    Perlin,
+}
+
+/// Options for a noise overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseOptions {
+    pub kind: NoiseKind,
+
+    /// Strength of the noise as a fraction of the full channel range
+    pub amount: f32,
+
+    /// Grain size in pixels
+    pub scale: f32,
+
+    /// Whether all channels get the same noise (grey grain) or independent noise
+    pub monochrome: bool,
+
+    /// Seed for the deterministic noise pattern
+    pub seed: u64,
+}
+
+impl Default for NoiseOptions {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::default(),
+            amount: 0.04,
+            scale: 1.0,
+            monochrome: true,
+            seed: 0,
+        }
+    }
+}
+
+impl NoiseOptions {
+    /// Parse noise options such as `perlin,amount=0.1,scale=8,chroma,seed=42`
+    ///
+    /// Bare entries select the kind (`uniform`, `gaussian`, `perlin`) or the
+    /// color mode (`mono`, `chroma`).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut options = Self::default();
+        let mut scale = None;
+
+        for (key, value) in parse_options(spec) {
+            let number = || parse_option_number(&format!("noise {}", key), value);
+
+            match (key.to_ascii_lowercase().as_str(), value) {
+                ("", value) => match value.to_ascii_lowercase().as_str() {
+                    "uniform" => options.kind = NoiseKind::Uniform,
+                    "gaussian" => options.kind = NoiseKind::Gaussian,
+                    "perlin" => options.kind = NoiseKind::Perlin,
+                    "mono" | "monochrome" => options.monochrome = true,
+                    "chroma" | "color" => options.monochrome = false,
+                    other => {
+                        return Err(anyhow!(
+                            "Unknown noise setting '{}' (expected uniform, gaussian, perlin, mono or chroma)",
+                            other
+                        ))
+                    }
+                },
+                ("amount", _) => options.amount = number()?,
+                ("scale", _) => scale = Some(number()?),
+                ("seed", value) => {
+                    options.seed = value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid noise seed '{}'", value))?
+                }
+                (other, _) => {
+                    return Err(anyhow!(
+                        "Unknown noise option '{}' (expected amount, scale or seed)",
+                        other
+                    ))
+                }
+            }
+        }
+
+        // Perlin noise sampled once per pixel is indistinguishable from white noise
+        options.scale = match (scale, options.kind) {
+            (Some(scale), _) => scale,
+            (None, NoiseKind::Perlin) => 4.0,
+            (None, _) => options.scale,
+        };
+        if options.scale <= 0.0 {
+            return Err(anyhow!("Noise scale must be positive"));
+        }
+
+        Ok(options)
+    }
+}
+
+/// Add noise to every pixel of `image` in place, leaving alpha untouched
+pub fn apply_noise(image: &mut RgbaImage, options: &NoiseOptions) {
+    debug!("Applying noise: {:?}", options);
+
+    let width = image.width() as usize;
+    if width == 0 || options.amount == 0.0 {
+        return;
+    }
+
+    image
+        .par_chunks_mut(width * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+                let grain = if options.monochrome {
+                    [sample(options, x, y, 0); 3]
+                } else {
+                    [0, 1, 2].map(|channel| sample(options, x, y, channel))
+                };
+
+                for (value, grain) in pixel.iter_mut().zip(grain) {
+                    let noisy = *value as f32 / 255.0 + grain * options.amount;
+                    *value = (noisy.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        });
+}
+
+/// Noise value around zero at pixel position (`x`, `y`) for one channel
+fn sample(options: &NoiseOptions, x: f32, y: f32, channel: u64) -> f32 {
+    let seed = splitmix64(options.seed ^ splitmix64(channel));
+    let (x, y) = (x / options.scale, y / options.scale);
+
+    match options.kind {
+        NoiseKind::Uniform => unit(hash(seed, x.floor(), y.floor())) * 2.0 - 1.0,
+        NoiseKind::Gaussian => {
+            // Box-Muller transform of two independent uniform samples
+            let h = hash(seed, x.floor(), y.floor());
+            let u1 = 1.0 - unit(h);
+            let u2 = unit(splitmix64(h));
+            (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+        }
+        NoiseKind::Perlin => perlin(seed, x, y),
+    }
+}
+
+/// Classic 2D Perlin gradient noise, scaled to roughly -1..1
+fn perlin(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    // Dot product of the hashed lattice gradient with the offset to the point
+    let corner = |cx: f32, cy: f32| {
+        let angle = unit(hash(seed, x0 + cx, y0 + cy)) * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        cos * (fx - cx) + sin * (fy - cy)
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let (u, v) = (fade(fx), fade(fy));
+    let top = lerp(corner(0.0, 0.0), corner(1.0, 0.0), u);
+    let bottom = lerp(corner(0.0, 1.0), corner(1.0, 1.0), u);
+
+    lerp(top, bottom, v) * std::f32::consts::SQRT_2
+}
+
+/// Hash a lattice point together with the seed
+fn hash(seed: u64, x: f32, y: f32) -> u64 {
+    let (x, y) = (x as i64 as u64, y as i64 as u64);
+    splitmix64(seed ^ splitmix64(x ^ y.rotate_left(32)))
+}
+
+/// Map a hash to a float in [0, 1)
+pub(crate) fn unit(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// SplitMix64 finalizer, a fast and well-mixed 64-bit hash
+pub(crate) fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, image::Rgba([128, 128, 128, 200]))
+    }
+
+    fn noisy(spec: &str) -> RgbaImage {
+        let mut image = grey(16, 16);
+        apply_noise(&mut image, &NoiseOptions::parse(spec).unwrap());
+        image
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(NoiseOptions::parse("").unwrap(), NoiseOptions::default());
+
+        let options = NoiseOptions::parse("Uniform, amount=0.1, scale=2, chroma, seed=7").unwrap();
+        assert_eq!(options.kind, NoiseKind::Uniform);
+        assert_eq!(options.amount, 0.1);
+        assert_eq!(options.scale, 2.0);
+        assert!(!options.monochrome);
+        assert_eq!(options.seed, 7);
+
+        // Perlin noise gets coarser grain unless a scale is given
+        assert_eq!(NoiseOptions::parse("perlin").unwrap().scale, 4.0);
+        assert_eq!(NoiseOptions::parse("perlin,scale=1").unwrap().scale, 1.0);
+        assert!(NoiseOptions::parse("chroma,mono").unwrap().monochrome);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let message = |spec| format!("{}", NoiseOptions::parse(spec).unwrap_err());
+
+        assert_eq!(
+            message("amount=-0.1"),
+            "Invalid noise amount '-0.1': expected a non-negative number"
+        );
+        assert_eq!(
+            message("scale=inf"),
+            "Invalid noise scale 'inf': expected a non-negative number"
+        );
+        assert_eq!(message("scale=0"), "Noise scale must be positive");
+        assert_eq!(message("seed=-1"), "Invalid noise seed '-1'");
+        assert_eq!(
+            message("pink"),
+            "Unknown noise setting 'pink' (expected uniform, gaussian, perlin, mono or chroma)"
+        );
+        assert_eq!(
+            message("octaves=3"),
+            "Unknown noise option 'octaves' (expected amount, scale or seed)"
+        );
+    }
+
+    #[test]
+    fn same_seed_gives_same_grain() {
+        for kind in ["uniform", "gaussian", "perlin"] {
+            let spec = format!("{},amount=0.2,chroma,seed=42", kind);
+            assert_eq!(noisy(&spec), noisy(&spec), "{} noise", kind);
+
+            let other = format!("{},amount=0.2,chroma,seed=43", kind);
+            assert_ne!(noisy(&spec), noisy(&other), "{} noise", kind);
+        }
+    }
+
+    #[test]
+    fn grain_does_not_depend_on_the_image_size() {
+        let small = noisy("gaussian,amount=0.2,seed=3");
+        let mut large = grey(32, 24);
+        apply_noise(
+            &mut large,
+            &NoiseOptions::parse("gaussian,amount=0.2,seed=3").unwrap(),
+        );
+
+        for (x, y, pixel) in small.enumerate_pixels() {
+            assert_eq!(large.get_pixel(x, y), pixel);
+        }
+    }
+
+    #[test]
+    fn monochrome_grain_is_grey_and_keeps_alpha() {
+        let image = noisy("uniform,amount=0.3,seed=1");
+        assert!(image.pixels().any(|p| p.0[0] != 128));
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0;
+            assert!(r == g && g == b);
+            assert_eq!(a, 200);
+        }
+
+        let image = noisy("uniform,amount=0.3,chroma,seed=1");
+        assert!(image.pixels().any(|p| p.0[0] != p.0[1]));
+    }
+
+    #[test]
+    fn zero_amount_leaves_the_image_alone() {
+        assert_eq!(noisy("amount=0"), grey(16, 16));
+    }
+}
@@ -0,0 +1,187 @@
+//! Palette extraction and automatic backgrounds
+//!
+//! The palette is found with median cut: the sampled pixels start out in one
+//! box that is repeatedly split at the median of its widest channel, and every
+//! final box is averaged into one color. It is deterministic, so the same
+//! screenshot always gets the same background.
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use rgb::RGBA8;
+
+use crate::color::{linear_srgb_to_oklab, oklab_to_srgb, srgb_to_linear, to_rgba8};
+
+/// Number of colors extracted for automatic backgrounds and `--print-palette`
+pub const PALETTE_SIZE: usize = 5;
+
+/// Upper bound on the number of pixels sampled from large images
+const MAX_SAMPLES: u64 = 65_536;
+
+/// Pixels more transparent than this are left out of the palette
+const MIN_ALPHA: u8 = 128;
+
+/// A color of an extracted palette
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaletteColor {
+    pub color: RGBA8,
+
+    /// Share of the sampled pixels that fell into this color (0.0-1.0)
+    pub weight: f32,
+}
+
+impl PaletteColor {
+    /// The color as a `#rrggbb` hex string
+    pub fn hex(&self) -> String {
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            self.color.r, self.color.g, self.color.b
+        )
+    }
+}
+
+/// How an automatic background is derived from the palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoMode {
+    /// Solid fill with the most common color
+    #[default]
+    Dominant,
+
+    /// Solid fill with the opposite hue of the most common color
+    Complementary,
+
+    /// Top-to-bottom gradient between the two most common colors
+    Gradient,
+}
+
+impl AutoMode {
+    /// Parse a mode name; an empty name is the default
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "" | "dominant" => Ok(AutoMode::Dominant),
+            "complementary" | "complement" => Ok(AutoMode::Complementary),
+            "gradient" => Ok(AutoMode::Gradient),
+            other => Err(anyhow!(
+                "Unknown auto background mode '{}' (expected dominant, complementary or gradient)",
+                other
+            )),
+        }
+    }
+}
+
+/// Extract up to `size` colors from `image`, most common first
+pub fn extract_palette(image: &RgbaImage, size: usize) -> Vec<PaletteColor> {
+    let pixel_count = image.width() as u64 * image.height() as u64;
+    let step = (pixel_count / MAX_SAMPLES).max(1) as usize;
+
+    let pixels = image
+        .pixels()
+        .step_by(step)
+        .filter(|p| p[3] >= MIN_ALPHA)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect::<Vec<_>>();
+    if pixels.is_empty() || size == 0 {
+        return Vec::new();
+    }
+
+    let total = pixels.len() as f32;
+    let mut boxes = vec![pixels];
+
+    while boxes.len() < size {
+        // Split the box with the most spread, weighted by how many pixels it holds
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (range > 0).then_some((i, channel, range as usize * pixels.len()))
+            })
+            .max_by_key(|&(_, _, priority)| priority)
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|p| p[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    let mut palette = boxes
+        .iter()
+        .map(|pixels| PaletteColor {
+            color: average(pixels),
+            weight: pixels.len() as f32 / total,
+        })
+        .collect::<Vec<_>>();
+    palette.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+    palette
+}
+
+/// Build the background specification for `mode` from the colors of `image`
+///
+/// Solid modes return a color (`#rrggbb`), the gradient mode a comma-separated
+/// gradient, ready for the color and gradient backgrounds.
+pub fn auto_background_spec(image: &RgbaImage, mode: AutoMode) -> Result<String> {
+    let palette = extract_palette(image, PALETTE_SIZE);
+    let dominant = palette.first().ok_or_else(|| {
+        anyhow!("Cannot pick an automatic background: the image has no opaque pixels")
+    })?;
+
+    Ok(match mode {
+        AutoMode::Dominant => dominant.hex(),
+        AutoMode::Complementary => PaletteColor {
+            color: complementary(dominant.color),
+            ..*dominant
+        }
+        .hex(),
+        AutoMode::Gradient => {
+            let second = palette.get(1).unwrap_or(dominant);
+            format!("{}, {}", dominant.hex(), second.hex())
+        }
+    })
+}
+
+/// Index and size of the channel with the largest value range
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), p| {
+                (min.min(p[channel]), max.max(p[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+/// Mean color of a box of pixels
+fn average(pixels: &[[u8; 3]]) -> RGBA8 {
+    let sums = pixels.iter().fold([0u64; 3], |mut sums, p| {
+        for (sum, &value) in sums.iter_mut().zip(p) {
+            *sum += value as u64;
+        }
+        sums
+    });
+    let count = pixels.len().max(1) as u64;
+
+    RGBA8::new(
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+        255,
+    )
+}
+
+/// The color with the opposite hue and the same lightness and chroma
+///
+/// In Oklab this is a point reflection through the grey axis.
+fn complementary(color: RGBA8) -> RGBA8 {
+    let [r, g, b] = [color.r, color.g, color.b].map(|c| srgb_to_linear(c as f32 / 255.0));
+    let (l, a, b) = linear_srgb_to_oklab(r, g, b);
+    let (r, g, b) = oklab_to_srgb(l, -a, -b);
+
+    to_rgba8(r, g, b, color.a as f32 / 255.0)
+}
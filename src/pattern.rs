@@ -0,0 +1,282 @@
+//! Procedural pattern backgrounds
+//!
+//! Dots, grid lines, stripes and checkerboards are rendered analytically at
+//! any canvas size, with each pixel's coverage computed from its distance to
+//! the shape so edges stay anti-aliased. Patterns are centred on the canvas
+//! and rotate around its centre. Options are a comma-separated list, e.g.
+//! `spacing=24,size=2,color=#ccc,background=white,angle=45deg`.
+
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use log::debug;
+use rgb::RGBA8;
+
+use crate::color::{parse_angle, parse_color};
+use crate::gradient::render;
+use crate::utils::{parse_option_number, parse_options};
+
+/// Kinds of procedural patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternKind {
+    /// A dot in the centre of every cell
+    Dots,
+
+    /// Horizontal and vertical lines
+    Grid,
+
+    /// Parallel bands
+    Stripes,
+
+    /// Alternating square cells
+    Checkerboard,
+}
+
+/// Options for a pattern background
+#[derive(Debug, Clone)]
+pub struct PatternOptions {
+    /// Distance between repeats in pixels
+    pub spacing: f32,
+
+    /// Dot radius, line width or stripe width in pixels; unused by checkerboards
+    pub size: f32,
+
+    /// Color of the dots, lines, stripes or odd cells
+    pub color: String,
+
+    /// Color behind the pattern
+    pub background: String,
+
+    /// Rotation in degrees, clockwise
+    pub angle: f32,
+}
+
+impl PatternOptions {
+    /// Default options for `kind`
+    pub fn new(kind: PatternKind) -> Self {
+        let spacing = 24.0;
+        Self {
+            spacing,
+            size: match kind {
+                PatternKind::Dots => 1.5,
+                PatternKind::Grid => 1.0,
+                PatternKind::Stripes => spacing / 2.0,
+                PatternKind::Checkerboard => 0.0,
+            },
+            color: "#d4d4d4".to_string(),
+            background: "#f5f5f5".to_string(),
+            angle: 0.0,
+        }
+    }
+
+    /// Parse pattern options on top of the defaults for `kind`
+    pub fn parse(kind: PatternKind, spec: &str) -> Result<Self> {
+        let mut options = Self::new(kind);
+        let mut size = None;
+
+        for (key, value) in parse_options(spec) {
+            let length = || {
+                let number = value.strip_suffix("px").unwrap_or(value);
+                parse_option_number(&format!("pattern {}", key), number)
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                "spacing" => options.spacing = length()?,
+                "size" => size = Some(length()?),
+                "color" | "fg" | "foreground" => options.color = value.to_string(),
+                "background" | "bg" => options.background = value.to_string(),
+                "angle" | "rotate" => options.angle = parse_angle(value, "angle")?,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown pattern option '{}' (expected spacing, size, color, background or angle)",
+                        if other.is_empty() { value } else { other }
+                    ))
+                }
+            }
+        }
+
+        if options.spacing <= 0.0 {
+            return Err(anyhow!("Pattern spacing must be positive"));
+        }
+
+        // Stripes default to half the spacing, so follow a changed spacing
+        options.size = match (size, kind) {
+            (Some(size), _) => size,
+            (None, PatternKind::Stripes) => options.spacing / 2.0,
+            (None, _) => options.size,
+        };
+
+        Ok(options)
+    }
+}
+
+/// Create a pattern background
+pub fn create_pattern(width: u32, height: u32, kind: PatternKind, spec: &str) -> Result<RgbaImage> {
+    let options = PatternOptions::parse(kind, spec)?;
+    debug!("Creating {:?} pattern background: {:?}", kind, options);
+
+    let foreground = parse_color(&options.color)?;
+    let background = parse_color(&options.background)?;
+
+    let (sin, cos) = options.angle.to_radians().sin_cos();
+    // Centre on a pixel centre so that odd-width lines land on whole pixels
+    let (cx, cy) = ((width / 2) as f32 + 0.5, (height / 2) as f32 + 0.5);
+    let spacing = options.spacing;
+    let half_size = options.size / 2.0;
+
+    Ok(render(width, height, |x, y| {
+        // Pattern coordinates, rotated around the canvas centre
+        let (dx, dy) = (x - cx, y - cy);
+        let u = dx * cos + dy * sin;
+        let v = -dx * sin + dy * cos;
+
+        let coverage = match kind {
+            PatternKind::Dots => {
+                let distance = distance_to_line(u, spacing).hypot(distance_to_line(v, spacing));
+                coverage(options.size - distance)
+            }
+            PatternKind::Grid => coverage(half_size - distance_to_line(u, spacing))
+                .max(coverage(half_size - distance_to_line(v, spacing))),
+            PatternKind::Stripes => coverage(half_size - distance_to_line(v, spacing)),
+            PatternKind::Checkerboard => {
+                let (a, b) = (checker(u, spacing), checker(v, spacing));
+                a * (1.0 - b) + b * (1.0 - a)
+            }
+        };
+
+        over(foreground, background, coverage)
+    }))
+}
+
+/// Distance from `t` to the nearest multiple of `spacing`
+fn distance_to_line(t: f32, spacing: f32) -> f32 {
+    (t - (t / spacing).round() * spacing).abs()
+}
+
+/// Pixel coverage of a shape whose edge is `distance` pixels away (positive inside)
+fn coverage(distance: f32) -> f32 {
+    (distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// Anti-aliased square wave: 1 in even cells along one axis, 0 in odd ones
+fn checker(t: f32, spacing: f32) -> f32 {
+    let phase = t.rem_euclid(2.0 * spacing);
+    let distance = if phase < spacing {
+        phase.min(spacing - phase)
+    } else {
+        -(phase - spacing).min(2.0 * spacing - phase)
+    };
+    coverage(distance)
+}
+
+/// Composite `foreground` with the given coverage over `background`, as straight 0-1 RGBA
+fn over(foreground: RGBA8, background: RGBA8, coverage: f32) -> [f32; 4] {
+    let fa = foreground.a as f32 / 255.0 * coverage;
+    let ba = background.a as f32 / 255.0;
+    let alpha = fa + ba * (1.0 - fa);
+    if alpha <= 0.0 {
+        return [0.0; 4];
+    }
+
+    let mix = |f: u8, b: u8| (f as f32 * fa + b as f32 * ba * (1.0 - fa)) / (255.0 * alpha);
+    [
+        mix(foreground.r, background.r),
+        mix(foreground.g, background.g),
+        mix(foreground.b, background.b),
+        alpha,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    fn pattern(kind: PatternKind, spec: &str) -> RgbaImage {
+        let spec = format!("color=black,background=white,{}", spec);
+        create_pattern(9, 9, kind, &spec).unwrap()
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = PatternOptions::parse(PatternKind::Dots, "").unwrap();
+        assert_eq!(options.spacing, 24.0);
+        assert_eq!(options.size, 1.5);
+
+        let options = PatternOptions::parse(
+            PatternKind::Grid,
+            "spacing=16px, size=2, fg=red, bg=#fff, rotate=0.25turn",
+        )
+        .unwrap();
+        assert_eq!(options.spacing, 16.0);
+        assert_eq!(options.size, 2.0);
+        assert_eq!(options.color, "red");
+        assert_eq!(options.background, "#fff");
+        assert_eq!(options.angle, 90.0);
+    }
+
+    #[test]
+    fn stripes_follow_the_spacing() {
+        let options = PatternOptions::parse(PatternKind::Stripes, "spacing=10").unwrap();
+        assert_eq!(options.size, 5.0);
+
+        let options = PatternOptions::parse(PatternKind::Stripes, "spacing=10,size=1").unwrap();
+        assert_eq!(options.size, 1.0);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let message = |spec| {
+            format!(
+                "{}",
+                PatternOptions::parse(PatternKind::Dots, spec).unwrap_err()
+            )
+        };
+
+        assert_eq!(
+            message("spacing=-4px"),
+            "Invalid pattern spacing '-4': expected a non-negative number"
+        );
+        assert_eq!(
+            message("size=big"),
+            "Invalid pattern size 'big': expected a non-negative number"
+        );
+        assert_eq!(message("spacing=0"), "Pattern spacing must be positive");
+        assert_eq!(
+            message("dense"),
+            "Unknown pattern option 'dense' (expected spacing, size, color, background or angle)"
+        );
+        assert_eq!(
+            message("gap=4"),
+            "Unknown pattern option 'gap' (expected spacing, size, color, background or angle)"
+        );
+    }
+
+    #[test]
+    fn draws_dots_on_the_centre() {
+        let image = pattern(PatternKind::Dots, "spacing=8,size=2");
+        assert_eq!(image.get_pixel(4, 4).0, BLACK);
+        assert_eq!(image.get_pixel(0, 0).0, WHITE);
+    }
+
+    #[test]
+    fn rotates_stripes() {
+        let image = pattern(PatternKind::Stripes, "spacing=8");
+        assert_eq!(image.get_pixel(0, 4).0, BLACK);
+        assert_eq!(image.get_pixel(4, 0).0, WHITE);
+
+        let image = pattern(PatternKind::Stripes, "spacing=8,angle=90deg");
+        assert_eq!(image.get_pixel(4, 0).0, BLACK);
+        assert_eq!(image.get_pixel(0, 4).0, WHITE);
+    }
+
+    #[test]
+    fn alternates_checkerboard_cells() {
+        let image = pattern(PatternKind::Checkerboard, "spacing=4");
+        assert_eq!(image.get_pixel(2, 2).0, WHITE);
+        assert_eq!(image.get_pixel(6, 6).0, WHITE);
+        assert_eq!(image.get_pixel(6, 2).0, BLACK);
+        assert_eq!(image.get_pixel(2, 6).0, BLACK);
+    }
+}
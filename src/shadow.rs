@@ -1,14 +1,24 @@
 //! Shadow effects
+//!
+//! Shadows can be written like CSS `box-shadow` values: an x and y offset, an
+//! optional blur and spread and a color, e.g. `0 8px 24px -4px rgba(0, 0, 0, 0.25)`. Several
+//! comma-separated shadows stack, with the first one on top.
 
-use anyhow::Result;
-use image::{imageops, ImageBuffer, Rgba, RgbaImage};
-use log;
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
 use rayon::prelude::*;
-use rgb;
 
-use crate::background::parse_color;
-use crate::image_processing::to_image_rgba;
-use crate::utils::Point;
+use crate::blend::BlendMode;
+use crate::blur::{gaussian_blur, kernel_extent};
+use crate::color::{parse_color, quantize_dithered};
+use crate::compositor;
+use crate::utils::{split_top_level, Point};
+
+/// Largest blur radius or spread, in pixels, that a shadow may have
+const MAX_EXTENT: f32 = 65_536.0;
+
+/// Largest shadow layer, in pixels, that is rendered
+const MAX_PIXELS: i64 = 1 << 28;
 
 /// Shadow options for the image framer
 #[derive(Debug, Clone)]
@@ -19,15 +29,184 @@ pub struct ShadowOptions {
     /// Color of the shadow
     pub color: String,
 
-    /// Blur radius of the shadow
+    /// Blur radius of the shadow, as the standard deviation in pixels
     pub radius: f32,
 
     /// Opacity of the shadow (0.0-1.0)
     pub opacity: f32,
+
+    /// Pixels to grow (positive) or shrink (negative) the shadow by before blurring
+    pub spread: f32,
+
+    /// How the shadow mixes with the background beneath it
+    pub blend: BlendMode,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            offset: Point::new(25.0, -25.0),
+            color: "black".to_string(),
+            radius: 25.0,
+            opacity: 1.0,
+            spread: 0.0,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+impl ShadowOptions {
+    /// Parse a single shadow such as `0 8px 24px rgba(0, 0, 0, 0.25)`
+    ///
+    /// The first two lengths are the x and y offset, an optional third is the
+    /// CSS blur radius, which is twice the standard deviation used for
+    /// [`radius`](Self::radius), and an optional fourth is the spread. The
+    /// color may come before or after the lengths and defaults to black.
+    /// `opacity=` and `blend=` entries set the remaining fields. CSS `inset`
+    /// shadows are rejected.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut lengths = Vec::new();
+        let mut color = None;
+        let mut options = Self {
+            radius: 0.0,
+            ..Self::default()
+        };
+
+        for token in split_top_level(spec.trim(), ' ') {
+            if token.is_empty() {
+                continue;
+            }
+
+            if token.eq_ignore_ascii_case("inset") {
+                return Err(anyhow!("inset shadows are not supported"));
+            }
+
+            if let Some((key, value)) = token.split_once('=') {
+                match key.to_ascii_lowercase().as_str() {
+                    "opacity" => {
+                        options.opacity = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|opacity| (0.0..=1.0).contains(opacity))
+                            .ok_or_else(|| {
+                                anyhow!("Shadow opacity must be between 0 and 1: {}", value)
+                            })?
+                    }
+                    "blend" => options.blend = BlendMode::parse(value)?,
+                    other => {
+                        return Err(anyhow!(
+                            "Unknown shadow option '{}' (expected opacity or blend)",
+                            other
+                        ))
+                    }
+                }
+            } else if let Some(length) = parse_length(token) {
+                lengths.push(length);
+            } else if color.replace(token).is_some() {
+                return Err(anyhow!("A shadow takes a single color"));
+            }
+        }
+
+        let (x, y, blur, spread) = match lengths[..] {
+            [x, y] => (x, y, 0.0, 0.0),
+            [x, y, blur] => (x, y, blur, 0.0),
+            [x, y, blur, spread] => (x, y, blur, spread),
+            _ => {
+                return Err(anyhow!(
+                    "Expected an x and y offset, an optional blur and spread, got {} lengths",
+                    lengths.len()
+                ))
+            }
+        };
+        if blur < 0.0 {
+            return Err(anyhow!("Shadow blur cannot be negative: {}", blur));
+        }
+
+        if let Some(color) = color {
+            parse_color(color)?;
+            options.color = color.to_string();
+        }
+        options.offset = Point::new(x, y);
+        options.radius = blur / 2.0;
+        options.spread = spread;
+
+        Ok(options)
+    }
+}
+
+/// Parse a comma-separated list of shadows, from top to bottom
+pub fn parse_shadows(spec: &str) -> Result<Vec<ShadowOptions>> {
+    split_top_level(spec, ',')
+        .into_iter()
+        .map(|shadow| {
+            ShadowOptions::parse(shadow)
+                .with_context(|| format!("Invalid shadow '{}'", shadow.trim()))
+        })
+        .collect()
+}
+
+/// Parse a length in pixels, with or without a `px` suffix
+fn parse_length(token: &str) -> Option<f32> {
+    token
+        .strip_suffix("px")
+        .unwrap_or(token)
+        .parse::<f32>()
+        .ok()
+        .filter(|length| length.is_finite())
 }
 
 /// Add a drop shadow to an image
-pub fn add_drop_shadow(image: &RgbaImage, options: &ShadowOptions) -> Result<RgbaImage> {
+///
+/// The result covers the image and its blurred shadow, whatever the direction
+/// of the offset, and is returned with the position of the image's top left
+/// corner within it. So that a far offset cannot blow up its size, the result
+/// reaches at most the shadow's own size beyond the image on each side; a
+/// shadow further away is clipped.
+///
+/// The shadow's blend mode has nothing to mix with here; to blend the shadow
+/// with a background, composite the layer from [`create_shadow`] onto it
+/// before drawing the image.
+pub fn add_drop_shadow(
+    image: &RgbaImage,
+    options: &ShadowOptions,
+) -> Result<(RgbaImage, (i64, i64))> {
+    let (shadow, (shadow_x, shadow_y)) = create_shadow(image, options)?;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let (shadow_width, shadow_height) = (shadow.width() as i64, shadow.height() as i64);
+
+    // Bounding box of the image and its shadow, relative to the image
+    let left = shadow_x.clamp(-shadow_width, 0);
+    let top = shadow_y.clamp(-shadow_height, 0);
+    let right = shadow_x
+        .saturating_add(shadow_width)
+        .clamp(width, width + shadow_width);
+    let bottom = shadow_y
+        .saturating_add(shadow_height)
+        .clamp(height, height + shadow_height);
+
+    // Draw the shadow into a transparent layer covering both, then the
+    // original image on top
+    let mut layer = RgbaImage::new((right - left) as u32, (bottom - top) as u32);
+    compositor::over(
+        &mut layer,
+        &shadow,
+        shadow_x.saturating_sub(left),
+        shadow_y.saturating_sub(top),
+    );
+    compositor::over(&mut layer, image, -left, -top);
+
+    Ok((layer, (-left, -top)))
+}
+
+/// Render the drop shadow of an image on its own transparent layer
+///
+/// The layer holds the full blurred shadow and nothing else. Returns it
+/// together with the position of its top left corner relative to the image's,
+/// which is where it should be composited beneath the image.
+pub fn create_shadow(
+    image: &RgbaImage,
+    options: &ShadowOptions,
+) -> Result<(RgbaImage, (i64, i64))> {
     log::debug!(
         "Adding drop shadow with radius {} and offset ({}, {})",
         options.radius,
@@ -35,120 +214,386 @@ pub fn add_drop_shadow(image: &RgbaImage, options: &ShadowOptions) -> Result<Rgb
         options.offset.y
     );
 
+    if !options.offset.x.is_finite() || !options.offset.y.is_finite() {
+        return Err(anyhow!("Shadow offset must be finite"));
+    }
+    if !options.spread.is_finite() || options.spread.abs() > MAX_EXTENT {
+        return Err(anyhow!(
+            "Shadow spread must be a number between -{0} and {0}: {1}",
+            MAX_EXTENT,
+            options.spread
+        ));
+    }
+    if options.radius.is_finite() && options.radius > MAX_EXTENT {
+        return Err(anyhow!(
+            "Shadow radius must be at most {}: {}",
+            MAX_EXTENT,
+            options.radius
+        ));
+    }
+
     // Parse shadow color
     let shadow_color = parse_color(&options.color)?;
 
-    // Calculate dimensions for the shadow image
-    let shadow_width = image.width() + 2 * options.radius as u32;
-    let shadow_height = image.height() + 2 * options.radius as u32;
+    // A positive spread grows the silhouette; any spread needs at least one
+    // empty pixel around it to measure the edge from. The blur then spreads
+    // the mask by the reach of its kernel on every side.
+    let spread_extent = if options.spread != 0.0 {
+        options.spread.ceil().max(1.0) as i64
+    } else {
+        0
+    };
+    let extent = spread_extent + kernel_extent(options.radius) as i64;
+
+    // Split the offset into whole pixels and a sub-pixel remainder in [0, 1)
+    let (whole_x, whole_y) = (options.offset.x.floor(), options.offset.y.floor());
+    let fraction = (options.offset.x - whole_x, options.offset.y - whole_y);
 
-    // Create alpha mask from original image
-    // Position of the original image in the larger shadow canvas
-    let offset_x = options.offset.x as u32;
-    let offset_y = options.offset.y as u32;
+    // Size and position of the shadow relative to the image; a fractional
+    // offset spills into one more pixel
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let shadow_width = width + 2 * extent + (fraction.0 > 0.0) as i64;
+    let shadow_height = height + 2 * extent + (fraction.1 > 0.0) as i64;
+    if shadow_width * shadow_height > MAX_PIXELS {
+        return Err(anyhow!(
+            "Shadow of {}x{} pixels is too large; reduce its blur or spread",
+            shadow_width,
+            shadow_height
+        ));
+    }
+    let shadow_x = whole_x as i64 - extent;
+    let shadow_y = whole_y as i64 - extent;
 
     // Copy alpha channel to create the shadow mask
     log::trace!("Began copying alpha channel to create the shadow mask");
-    let alpha_mask = create_alpha_mask(&image, offset_x, offset_y, shadow_width, shadow_height);
+    let (shadow_width, shadow_height) = (shadow_width as u32, shadow_height as u32);
+    let mut alpha_mask =
+        create_alpha_mask(image, extent as f32, fraction, shadow_width, shadow_height);
+
+    // Grow or shrink the silhouette before it is blurred
+    if options.spread != 0.0 {
+        log::trace!("Spreading the shadow mask by {}px", options.spread);
+        spread_mask(
+            &mut alpha_mask,
+            shadow_width as usize,
+            shadow_height as usize,
+            options.spread,
+        );
+    }
 
     // Apply Gaussian blur to create the shadow effect
-    log::trace!("Applying Guassian blur");
-    image::imageops::blur(&alpha_mask, options.radius);
+    log::trace!("Applying Gaussian blur");
+    gaussian_blur(
+        &mut alpha_mask,
+        shadow_width as usize,
+        shadow_height as usize,
+        options.radius,
+    );
 
-    // Apply opacity to the blurred mask
+    // Apply opacity to the blurred mask, dithering the soft falloff
     log::trace!("Applying opacity to the blurred mask");
+    let opacity = options.opacity * shadow_color.a as f32 / 255.0;
     let mut shadow_image = RgbaImage::new(shadow_width, shadow_height);
-    for (x, y, pixel) in alpha_mask.enumerate_pixels() {
-        let alpha = (pixel[3] as f32 * options.opacity) as u8;
-        shadow_image.put_pixel(
-            x,
-            y,
-            Rgba([shadow_color.r, shadow_color.g, shadow_color.b, alpha]),
-        );
-    }
+    shadow_image
+        .par_chunks_mut(4)
+        .zip(alpha_mask.par_iter())
+        .enumerate()
+        .for_each(|(i, (pixel, alpha))| {
+            let (x, y) = (i as u32 % shadow_width, i as u32 / shadow_width);
+            pixel.copy_from_slice(&[
+                shadow_color.r,
+                shadow_color.g,
+                shadow_color.b,
+                quantize_dithered(alpha * opacity, x, y),
+            ]);
+        });
 
-    // Calculate dimensions for the final image (original + shadow with offset)
-    let final_width = shadow_width + options.offset.x.abs() as u32;
-    let final_height = shadow_height + options.offset.y.abs() as u32;
+    Ok((shadow_image, (shadow_x, shadow_y)))
+}
 
-    // Create the final image
-    let mut final_image = RgbaImage::new(final_width, final_height);
+/// Build a single-channel shadow mask (0.0-1.0) in parallel
+///
+/// The alpha of `image` is copied into a `shadow_width` x `shadow_height`
+/// buffer, inset by `padding` and shifted right and down by the sub-pixel
+/// `fraction` with bilinear interpolation; everything else is zero.
+fn create_alpha_mask(
+    image: &RgbaImage,
+    padding: f32,
+    fraction: (f32, f32),
+    shadow_width: u32,
+    shadow_height: u32,
+) -> Vec<f32> {
+    let mut mask = vec![0.0; (shadow_width * shadow_height) as usize];
 
-    // Calculate the position of the shadow in the final image
-    let shadow_pos_x = if options.offset.x < 0.0 {
-        (-options.offset.x) as u32
-    } else {
-        0
+    let alpha = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            0.0
+        } else {
+            image.get_pixel(x as u32, y as u32)[3] as f32 / 255.0
+        }
     };
-    let shadow_pos_y = if options.offset.y < 0.0 {
-        (-options.offset.y) as u32
-    } else {
-        0
+
+    mask.par_iter_mut().enumerate().for_each(|(i, value)| {
+        // Position in the image that lands on this mask pixel
+        let x = (i as u32 % shadow_width) as f32 - padding - fraction.0;
+        let y = (i as u32 / shadow_width) as f32 - padding - fraction.1;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let upper = alpha(x0, y0) * (1.0 - tx) + alpha(x0 + 1.0, y0) * tx;
+        let lower = alpha(x0, y0 + 1.0) * (1.0 - tx) + alpha(x0 + 1.0, y0 + 1.0) * tx;
+        *value = upper * (1.0 - ty) + lower * ty;
+    });
+
+    mask
+}
+
+/// Grow (positive `spread`) or shrink (negative) a mask by `spread` pixels
+///
+/// The mask's edge is moved along its Euclidean distance field, so rounded
+/// corners stay round and simply change radius, like a CSS `box-shadow`
+/// spread. Anti-aliased edges keep their sub-pixel coverage.
+fn spread_mask(mask: &mut [f32], width: usize, height: usize, spread: f32) {
+    let inside = mask.iter().map(|&alpha| alpha >= 0.5).collect::<Vec<_>>();
+    let outside = inside.iter().map(|&inside| !inside).collect::<Vec<_>>();
+
+    // Squared distances from every pixel to the nearest pixel of each kind
+    let to_outside = distance_squared(&outside, width, height);
+    let to_inside = distance_squared(&inside, width, height);
+
+    mask.par_iter_mut().enumerate().for_each(|(i, alpha)| {
+        // Signed distance from the pixel centre to the edge, positive inside
+        let distance = if inside[i] {
+            to_outside[i].sqrt() as f32 - 0.5
+        } else {
+            0.5 - to_inside[i].sqrt() as f32
+        };
+
+        // Right at the edge, the coverage is more precise than the distance
+        let distance = if distance.abs() <= 0.5 {
+            *alpha - 0.5
+        } else {
+            distance
+        };
+
+        *alpha = (distance + spread + 0.5).clamp(0.0, 1.0);
+    });
+}
+
+/// Stands in for an infinite distance while keeping the arithmetic finite
+const FAR: f64 = 1e12;
+
+/// Squared Euclidean distance from every pixel to the nearest `true` pixel
+///
+/// The exact two-pass transform from "Distance Transforms of Sampled
+/// Functions" (Felzenszwalb and Huttenlocher, 2012), run over rows and then
+/// columns in parallel.
+fn distance_squared(features: &[bool], width: usize, height: usize) -> Vec<f64> {
+    let mut grid = features
+        .iter()
+        .map(|&feature| if feature { 0.0 } else { FAR })
+        .collect::<Vec<_>>();
+
+    grid.par_chunks_mut(width).for_each(|row| {
+        let source = row.to_vec();
+        distance_transform_1d(&source, row);
+    });
+
+    let columns = (0..width)
+        .into_par_iter()
+        .map(|x| {
+            let source = (0..height).map(|y| grid[y * width + x]).collect::<Vec<_>>();
+            let mut column = vec![0.0; height];
+            distance_transform_1d(&source, &mut column);
+            column
+        })
+        .collect::<Vec<_>>();
+
+    grid.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (value, column) in row.iter_mut().zip(&columns) {
+            *value = column[y];
+        }
+    });
+
+    grid
+}
+
+/// One dimensional squared distance transform of the sampled function `f`
+///
+/// Computes the lower envelope of the parabolas rooted at each sample.
+fn distance_transform_1d(f: &[f64], distances: &mut [f64]) {
+    let n = f.len();
+    let mut roots = vec![0usize; n];
+    let mut boundaries = vec![0.0f64; n + 1];
+    let mut k = 0;
+    boundaries[0] = f64::NEG_INFINITY;
+    boundaries[1] = f64::INFINITY;
+
+    // Intersection of the parabolas rooted at `p` and `q`
+    let intersection = |p: usize, q: usize| {
+        ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2 * q - 2 * p) as f64
     };
 
-    // Draw the shadow
-    log::trace!("Drawing the image shadow");
-    for (x, y, pixel) in shadow_image.enumerate_pixels() {
-        let final_x = shadow_pos_x + x + options.offset.x.max(0.0) as u32;
-        let final_y = shadow_pos_y + y + options.offset.y.max(0.0) as u32;
+    for q in 1..n {
+        let mut s = intersection(roots[k], q);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(roots[k], q);
+        }
+        k += 1;
+        roots[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f64::INFINITY;
+    }
 
-        if final_x < final_width && final_y < final_height {
-            final_image.put_pixel(final_x, final_y, *pixel);
+    k = 0;
+    for (q, distance) in distances.iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f64 {
+            k += 1;
         }
+        let offset = q as f64 - roots[k] as f64;
+        *distance = offset * offset + f[roots[k]];
     }
+}
 
-    // Calculate the position of the original image in the final image
-    let image_pos_x = shadow_pos_x + offset_x;
-    let image_pos_y = shadow_pos_y + offset_y;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
 
-    // Draw the original image on top of the shadow
-    imageops::overlay(
-        &mut final_image,
-        image.into(),
-        image_pos_x.into(),
-        image_pos_y.into(),
-    );
+    fn shadow(x: f32, y: f32, radius: f32) -> ShadowOptions {
+        ShadowOptions {
+            offset: Point::new(x, y),
+            radius,
+            opacity: 1.0,
+            ..ShadowOptions::default()
+        }
+    }
 
-    Ok(final_image)
-}
+    #[test]
+    fn parses_box_shadow_syntax() {
+        let options = ShadowOptions::parse("2px -4 12px 3 #0008 opacity=0.5").unwrap();
+        assert_eq!((options.offset.x, options.offset.y), (2.0, -4.0));
+        assert_eq!((options.radius, options.spread), (6.0, 3.0));
+        assert_eq!((options.color.as_str(), options.opacity), ("#0008", 0.5));
 
-/// Build a shadow‐alpha mask in parallel.
-///
-/// For each shadow‐pixel index `i`:
-///   let s_x = i % shadow_width;  s_y = i / shadow_width;
-///   if s_x>=offset_x && s_y>=offset_y,
-///     let x = s_x-offset_x;  y = s_y-offset_y;
-///     if (x,y) in source `image` bounds, read its alpha channel
-///       \(\alpha = p[3]/255.0\) and write RGBA = [255,255,255, (α*255.0) as u8].
-fn create_alpha_mask(
-    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
-    offset_x: u32,
-    offset_y: u32,
-    shadow_width: u32,
-    shadow_height: u32,
-) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    // preallocate a flat RGBA buffer
-    let mut buf = vec![0u8; (shadow_width * shadow_height * 4) as usize];
-
-    buf.par_chunks_mut(4).enumerate().for_each(|(i, pixel)| {
-        let s_x = (i as u32) % shadow_width;
-        let s_y = (i as u32) / shadow_width;
-        if s_x >= offset_x && s_y >= offset_y {
-            let x = s_x - offset_x;
-            let y = s_y - offset_y;
-            if x < image.width() && y < image.height() {
-                let p = image.get_pixel(x, y);
-                // normalize alpha: α = p[3]/255.0
-                let alpha = p[3] as f32 / 255.0;
-                pixel[0] = 255;
-                pixel[1] = 255;
-                pixel[2] = 255;
-                pixel[3] = (alpha * 255.0) as u8;
+        let error = ShadowOptions::parse("inset 0 2px 4px black").unwrap_err();
+        assert_eq!(error.to_string(), "inset shadows are not supported");
+    }
+
+    #[test]
+    fn distance_transform_finds_nearest_feature() {
+        let mut distances = [0.0; 4];
+        distance_transform_1d(&[FAR, 0.0, FAR, FAR], &mut distances);
+        assert_eq!(distances, [1.0, 0.0, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn spread_grows_a_pixel_into_a_disc() {
+        let (size, centre) = (9, 4);
+        let mut mask = vec![0.0; size * size];
+        mask[centre * size + centre] = 1.0;
+        spread_mask(&mut mask, size, size, 2.0);
+
+        for y in 0..size {
+            for x in 0..size {
+                let distance = (x as f32 - centre as f32).hypot(y as f32 - centre as f32);
+                let alpha = mask[y * size + x];
+                if distance <= 2.0 {
+                    assert_eq!(alpha, 1.0, "({}, {}) inside the disc", x, y);
+                } else if distance >= 3.0 {
+                    assert_eq!(alpha, 0.0, "({}, {}) outside the disc", x, y);
+                }
             }
         }
-    });
 
-    // reconstruct an ImageBuffer from the raw Vec<u8>
-    ImageBuffer::from_raw(shadow_width, shadow_height, buf)
-        .expect("buffer size must match dimensions")
+        // The edge lies 2.5px from the centre, so a pixel √5 away is about 0.76 covered
+        assert!((mask[(centre + 1) * size + centre + 2] - 0.76).abs() < 0.01);
+    }
+
+    #[test]
+    fn negative_spread_erodes_a_block() {
+        let size = 5;
+        let mut mask = vec![0.0; size * size];
+        for y in 1..4 {
+            for x in 1..4 {
+                mask[y * size + x] = 1.0;
+            }
+        }
+        spread_mask(&mut mask, size, size, -1.0);
+
+        let mut expected = vec![0.0; size * size];
+        expected[2 * size + 2] = 1.0;
+        assert_eq!(mask, expected);
+    }
+
+    #[test]
+    fn shadow_layer_is_placed_by_offset() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+
+        let (layer, position) = create_shadow(&image, &shadow(5.0, -3.0, 0.0)).unwrap();
+        assert_eq!(layer.dimensions(), (10, 10));
+        assert_eq!(position, (5, -3));
+
+        let extent = kernel_extent(2.0) as i64;
+        let (layer, position) = create_shadow(&image, &shadow(5.0, -3.0, 2.0)).unwrap();
+        assert_eq!(layer.width() as i64, 10 + 2 * extent);
+        assert_eq!(position, (5 - extent, -3 - extent));
+    }
+
+    #[test]
+    fn far_offsets_do_not_grow_the_layer() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let options = shadow(1e9, -1e9, 0.0);
+
+        let (layer, _) = create_shadow(&image, &options).unwrap();
+        assert_eq!(layer.dimensions(), (10, 10));
+
+        // The shadow is clipped away and the image kept
+        let (framed, position) = add_drop_shadow(&image, &options).unwrap();
+        assert_eq!(framed.dimensions(), (20, 20));
+        assert_eq!(position, (0, 10));
+        assert_eq!(*framed.get_pixel(0, 10), Rgba([255, 255, 255, 255]));
+        assert!(framed.pixels().filter(|pixel| pixel[3] > 0).count() == 100);
+    }
+
+    #[test]
+    fn rejects_unbounded_spread() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        for spread in [f32::INFINITY, f32::NAN, 1e7] {
+            let options = ShadowOptions {
+                spread,
+                ..shadow(0.0, 0.0, 0.0)
+            };
+            assert!(
+                create_shadow(&image, &options).is_err(),
+                "spread {}",
+                spread
+            );
+        }
+        assert!(ShadowOptions::parse("0 0 0 inf").is_err());
+    }
+
+    #[test]
+    fn drop_shadow_grows_past_small_images() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let (framed, position) = add_drop_shadow(&image, &shadow(6.0, 0.0, 0.0)).unwrap();
+
+        assert_eq!(framed.dimensions(), (8, 4));
+        assert_eq!(position, (0, 0));
+        assert_eq!(framed.get_pixel(5, 0)[3], 0);
+        assert_eq!(framed.get_pixel(7, 0)[3], 255);
+    }
+
+    #[test]
+    fn drop_shadow_covers_image_and_shadow() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        let (framed, position) = add_drop_shadow(&image, &shadow(-4.0, 6.0, 0.0)).unwrap();
+
+        assert_eq!(framed.dimensions(), (14, 16));
+        assert_eq!(position, (4, 0));
+        assert_eq!(*framed.get_pixel(4, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*framed.get_pixel(0, 15), Rgba([0, 0, 0, 255]));
+        assert_eq!(framed.get_pixel(0, 0)[3], 0);
+    }
 }
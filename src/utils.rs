@@ -1,8 +1,6 @@
 //! Utility functions and types
 
-use anyhow::Result;
-use image::Rgba;
-use tempfile::NamedTempFile;
+use anyhow::{anyhow, Result};
 
 /// A 2D point with floating-point coordinates
 #[derive(Debug, Clone, Copy)]
@@ -18,39 +16,76 @@ impl Point {
     }
 
     /// Convert to integer coordinates
-    pub fn to_i32(&self) -> (i32, i32) {
+    pub fn to_i32(self) -> (i32, i32) {
         (self.x as i32, self.y as i32)
     }
 
     /// Convert to unsigned integer coordinates
-    pub fn to_u32(&self) -> (u32, u32) {
+    pub fn to_u32(self) -> (u32, u32) {
         (self.x.max(0.0) as u32, self.y.max(0.0) as u32)
     }
 }
 
-/// Create a temporary file with the given extension
-pub fn create_temp_file(extension: &str) -> Result<NamedTempFile> {
-    let temp_file = tempfile::Builder::new()
-        .suffix(&format!(".{}", extension))
-        .tempfile()?;
-    Ok(temp_file)
-}
-
 /// Type representing corner radii for all four corners
 /// Ordered as: (top_left, top_right, bottom_right, bottom_left)
 pub type CornerRadii = (u32, u32, u32, u32);
 
-/// Blend two colors based on alpha
-pub fn blend_color(color1: Rgba<u8>, color2: Rgba<u8>, alpha: f32) -> Rgba<u8> {
-    let blend =
-        |a: u8, b: u8, alpha: f32| -> u8 { (a as f32 * (1.0 - alpha) + b as f32 * alpha) as u8 };
-
-    Rgba([
-        blend(color1[0], color2[0], alpha),
-        blend(color1[1], color2[1], alpha),
-        blend(color1[2], color2[2], alpha),
-        blend(color1[3], color2[3], alpha),
-    ])
+/// Split `input` on `separator`, ignoring separators nested inside parentheses
+///
+/// This keeps functional colors such as `hsl(-20 50% 50%)` in one piece.
+pub fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                parts.push(&input[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+
+    parts
+}
+
+/// Split a comma-separated option list such as `radius=40,brightness=0.6`
+///
+/// Returns trimmed `(key, value)` pairs; an entry without `=` has an empty key,
+/// which callers may treat as a positional shorthand.
+pub fn parse_options(input: &str) -> Vec<(&str, &str)> {
+    split_top_level(input, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => ("", entry),
+        })
+        .collect()
+}
+
+/// Parse the value of a numeric option from [`parse_options`]
+///
+/// Accepts finite, non-negative numbers; `what` names the option in the error,
+/// e.g. `noise amount`.
+pub fn parse_option_number(what: &str, value: &str) -> Result<f32> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid {} '{}': expected a non-negative number",
+                what,
+                value
+            )
+        })
 }
 
 /// Calculate padding to maintain aspect ratio